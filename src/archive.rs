@@ -0,0 +1,474 @@
+use flate2::read::GzDecoder;
+use std::io::{Cursor, Read};
+use tar::Archive as TarArchive;
+use zip::ZipArchive;
+
+/// Separator placed between an archive's path and the path of one of its members
+pub const MEMBER_SEPARATOR: &str = "!/";
+
+const ZIP_EXTENSIONS: &[&str] = &[
+    ".zip", ".jar", ".war", ".ear", ".aar", ".apk", ".nupkg", ".snupkg", ".whl", ".vsix",
+];
+const TAR_GZ_EXTENSIONS: &[&str] = &[".tar.gz", ".tgz"];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ArchiveKind {
+    Zip,
+    Tar,
+    TarGz,
+    Gz,
+}
+
+/// Limits applied while unpacking an archive (and any archives nested inside of it)
+#[derive(Clone, Debug)]
+pub struct ArchiveLimits {
+    /// Largest archive or member, in bytes, that will be read
+    pub max_size: u64,
+    /// Total number of bytes that may be extracted from a single top-level archive
+    pub max_total_size: u64,
+    /// How many levels of archives will be unpacked -- 1 only unpacks archives committed directly
+    pub max_depth: usize,
+    /// Largest compression ratio allowed for a member before it's treated as a zip bomb
+    pub max_ratio: u64,
+    /// Most members that will be read from a single top-level archive
+    pub max_entries: usize,
+}
+
+impl Default for ArchiveLimits {
+    fn default() -> ArchiveLimits {
+        ArchiveLimits {
+            max_size: 50 * 1024 * 1024,
+            max_total_size: 200 * 1024 * 1024,
+            max_depth: 3,
+            max_ratio: 100,
+            max_entries: 10_000,
+        }
+    }
+}
+
+pub struct ArchiveEntry {
+    /// Path of the member relative to the top-level archive, e.g. `lib/inner.jar!/app.config`
+    pub path: String,
+    pub data: Vec<u8>,
+}
+
+/// Members read from an archive, and the ones that were left out because of a limit
+pub struct Extracted {
+    pub entries: Vec<ArchiveEntry>,
+    /// Path of each member that wasn't read (or unpacked, for nested archives) and why
+    pub skipped: Vec<(String, String)>,
+}
+
+struct Budget<'a> {
+    limits: &'a ArchiveLimits,
+    bytes: u64,
+    entries: usize,
+    skipped: Vec<(String, String)>,
+}
+
+impl<'a> Budget<'a> {
+    fn new(limits: &'a ArchiveLimits) -> Budget<'a> {
+        Budget {
+            limits,
+            bytes: 0,
+            entries: 0,
+            skipped: Vec::new(),
+        }
+    }
+
+    // Leave out a single member, which still counts towards the number of entries
+    fn skip(&mut self, name: &str, reason: String) -> Result<(), String> {
+        self.skipped.push((name.to_owned(), reason));
+        self.take(0)
+    }
+
+    // Returns how many bytes may still be read for a single member
+    fn remaining(&self) -> u64 {
+        ::std::cmp::min(
+            self.limits.max_size,
+            self.limits.max_total_size.saturating_sub(self.bytes),
+        )
+    }
+
+    fn take(&mut self, size: u64) -> Result<(), String> {
+        self.entries += 1;
+        if self.entries > self.limits.max_entries {
+            return Err(format!(
+                "archive contains more than {} entries",
+                self.limits.max_entries
+            ));
+        }
+
+        self.bytes += size;
+        if self.bytes > self.limits.max_total_size {
+            return Err(format!(
+                "archive expands to more than {} bytes",
+                self.limits.max_total_size
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+pub fn is_archive(file_name: &str) -> bool {
+    archive_kind(file_name).is_some()
+}
+
+fn archive_kind(file_name: &str) -> Option<ArchiveKind> {
+    let file_name = file_name.to_lowercase();
+
+    if ZIP_EXTENSIONS.iter().any(|ext| file_name.ends_with(ext)) {
+        Some(ArchiveKind::Zip)
+    } else if TAR_GZ_EXTENSIONS.iter().any(|ext| file_name.ends_with(ext)) {
+        Some(ArchiveKind::TarGz)
+    } else if file_name.ends_with(".tar") {
+        Some(ArchiveKind::Tar)
+    } else if file_name.ends_with(".gz") {
+        Some(ArchiveKind::Gz)
+    } else {
+        None
+    }
+}
+
+//
+// Unpack an archive from memory. Nested archives are unpacked up to `limits.max_depth` levels
+// deep and their members are returned alongside the archive's own members. Members that are too
+// large or compressed too well are skipped so the rest can still be scanned, but an error is
+// returned if the archive as a whole goes over the limits -- entries read before that point are
+// discarded.
+//
+pub fn extract(file_name: &str, data: &[u8], limits: &ArchiveLimits) -> Result<Extracted, String> {
+    if data.len() as u64 > limits.max_size {
+        return Err(format!("archive is larger than {} bytes", limits.max_size));
+    }
+
+    let mut budget = Budget::new(limits);
+    let mut entries = Vec::new();
+    extract_into(file_name, data, "", 0, &mut budget, &mut entries)?;
+
+    Ok(Extracted {
+        entries,
+        skipped: budget.skipped,
+    })
+}
+
+//
+// Read the members of a zip file without unpacking any archives nested inside of it
+//
+pub fn extract_zip(data: &[u8], limits: &ArchiveLimits) -> Result<Vec<ArchiveEntry>, String> {
+    let mut budget = Budget::new(limits);
+
+    Ok(read_zip(data, &mut budget)?
+        .into_iter()
+        .map(|(path, data)| ArchiveEntry { path, data })
+        .collect())
+}
+
+fn extract_into(
+    file_name: &str,
+    data: &[u8],
+    prefix: &str,
+    depth: usize,
+    budget: &mut Budget,
+    entries: &mut Vec<ArchiveEntry>,
+) -> Result<(), String> {
+    let members = match archive_kind(file_name) {
+        Some(ArchiveKind::Zip) => read_zip(data, budget)?,
+        Some(ArchiveKind::Tar) => read_tar(Cursor::new(data), budget)?,
+        // the tar stream is read as it's decompressed, so only its members count towards the
+        // budget. It's still capped, for streams of nothing but padding.
+        Some(ArchiveKind::TarGz) => {
+            let cap = budget.limits.max_total_size + budget.limits.max_entries as u64 * 1024;
+            read_tar(GzDecoder::new(data).take(cap), budget)?
+        }
+        Some(ArchiveKind::Gz) => {
+            let inner_name = file_name
+                .rsplit('/')
+                .next()
+                .unwrap()
+                .trim_end_matches(".gz")
+                .trim_end_matches(".GZ");
+            read_gz(inner_name, data, budget)?
+                .map(|contents| vec![(inner_name.to_string(), contents)])
+                .unwrap_or_default()
+        }
+        None => return Ok(()),
+    };
+
+    for (name, member_data) in members {
+        let path = format!("{}{}", prefix, name);
+
+        if is_archive(&name) {
+            if depth + 1 < budget.limits.max_depth {
+                let nested_prefix = format!("{}{}", path, MEMBER_SEPARATOR);
+                extract_into(
+                    &name,
+                    &member_data,
+                    &nested_prefix,
+                    depth + 1,
+                    budget,
+                    entries,
+                )?;
+            } else {
+                budget.skipped.push((
+                    path.clone(),
+                    format!(
+                        "archive nested more than {} level(s) deep",
+                        budget.limits.max_depth
+                    ),
+                ));
+            }
+        }
+
+        entries.push(ArchiveEntry {
+            path,
+            data: member_data,
+        });
+    }
+
+    Ok(())
+}
+
+fn read_zip(data: &[u8], budget: &mut Budget) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let mut archive = match ZipArchive::new(Cursor::new(data)) {
+        Ok(archive) => archive,
+        // not a valid zip -- nothing to scan
+        Err(_) => return Ok(Vec::new()),
+    };
+
+    let mut members = Vec::new();
+    for i in 0..archive.len() {
+        let mut file = match archive.by_index(i) {
+            Ok(file) => file,
+            Err(_) => continue,
+        };
+
+        if file.is_dir() {
+            continue;
+        }
+
+        let name = file.name().to_string();
+        let compressed_size = ::std::cmp::max(file.compressed_size(), 1);
+        if file.size() / compressed_size > budget.limits.max_ratio {
+            let reason = format!(
+                "compression ratio is higher than {}",
+                budget.limits.max_ratio
+            );
+            budget.skip(&name, reason)?;
+            continue;
+        }
+
+        if let Some(contents) = read_member(&mut file, &name, budget)? {
+            members.push((name, contents));
+        }
+    }
+
+    Ok(members)
+}
+
+fn read_tar<R: Read>(data: R, budget: &mut Budget) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let mut archive = TarArchive::new(data);
+    let entries = match archive.entries() {
+        Ok(entries) => entries,
+        Err(_) => return Ok(Vec::new()),
+    };
+
+    let mut members = Vec::new();
+    for entry in entries {
+        let mut entry = match entry {
+            Ok(entry) => entry,
+            // a corrupt header means we can't find the next entry either
+            Err(_) => break,
+        };
+
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+
+        let name = match entry.path() {
+            Ok(path) => path.to_string_lossy().into_owned(),
+            Err(_) => continue,
+        };
+        if let Some(contents) = read_member(&mut entry, &name, budget)? {
+            members.push((name, contents));
+        }
+    }
+
+    Ok(members)
+}
+
+fn read_gz(name: &str, data: &[u8], budget: &mut Budget) -> Result<Option<Vec<u8>>, String> {
+    let mut decoder = GzDecoder::new(data);
+    let contents = match read_member(&mut decoder, name, budget)? {
+        Some(contents) => contents,
+        None => return Ok(None),
+    };
+
+    if contents.len() as u64 / ::std::cmp::max(data.len() as u64, 1) > budget.limits.max_ratio {
+        let reason = format!(
+            "compression ratio is higher than {}",
+            budget.limits.max_ratio
+        );
+        budget.skipped.push((name.to_owned(), reason));
+        return Ok(None);
+    }
+
+    Ok(Some(contents))
+}
+
+//
+// Reads at most the remaining budget from `reader`. A member larger than `max_size` is skipped,
+// but running out of the archive's total budget is an error.
+//
+fn read_member<R: Read>(
+    reader: &mut R,
+    name: &str,
+    budget: &mut Budget,
+) -> Result<Option<Vec<u8>>, String> {
+    let limit = budget.remaining();
+    let mut contents = Vec::new();
+
+    // a read error part of the way through (e.g. a truncated stream) still leaves us
+    // with something worth scanning
+    let _ = reader.take(limit + 1).read_to_end(&mut contents);
+    if contents.len() as u64 > limit {
+        if limit < budget.limits.max_size {
+            return Err(format!(
+                "archive expands to more than {} bytes",
+                budget.limits.max_total_size
+            ));
+        }
+
+        budget.skip(name, format!("larger than {} bytes", limit))?;
+        return Ok(None);
+    }
+
+    budget.take(contents.len() as u64)?;
+
+    Ok(Some(contents))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+    use tar::{Builder, Header};
+    use zip::write::FileOptions;
+    use zip::{CompressionMethod, ZipWriter};
+
+    fn zip(members: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        for &(name, data) in members {
+            writer.start_file(name, options).unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn tar_gz(members: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        for &(name, data) in members {
+            let mut header = Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, data).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    fn paths(extracted: &Extracted) -> Vec<&str> {
+        extracted.entries.iter().map(|e| e.path.as_str()).collect()
+    }
+
+    #[test]
+    fn skips_members_over_the_compression_ratio() {
+        let zeros = vec![0; 1024 * 1024];
+        let data = zip(&[("zeros.bin", &zeros), ("app.config", b"password=hunter2")]);
+
+        let extracted = extract("a.zip", &data, &ArchiveLimits::default()).unwrap();
+        assert_eq!(paths(&extracted), vec!["app.config"]);
+        assert_eq!(extracted.skipped.len(), 1);
+        assert_eq!(extracted.skipped[0].0, "zeros.bin");
+    }
+
+    #[test]
+    fn skips_members_over_the_size_limit() {
+        // compresses well enough to keep the archive itself under the limit
+        let big: Vec<u8> = (0..5000).map(|i| (i * 7 % 256) as u8).collect();
+        let data = zip(&[("big.txt", &big), ("small.txt", b"0123")]);
+        let limits = ArchiveLimits {
+            max_size: 1000,
+            ..ArchiveLimits::default()
+        };
+        assert!(data.len() < 1000);
+
+        let extracted = extract("a.zip", &data, &limits).unwrap();
+        assert_eq!(paths(&extracted), vec!["small.txt"]);
+        assert_eq!(extracted.skipped[0].0, "big.txt");
+    }
+
+    #[test]
+    fn reports_archives_nested_past_the_depth_limit() {
+        let deep = zip(&[("secret.txt", b"deep")]);
+        let inner = zip(&[("deep.zip", &deep), ("inner.txt", b"inner")]);
+        let outer = zip(&[("inner.zip", &inner)]);
+        let limits = ArchiveLimits {
+            max_depth: 2,
+            ..ArchiveLimits::default()
+        };
+
+        let extracted = extract("outer.zip", &outer, &limits).unwrap();
+        assert!(paths(&extracted).contains(&"inner.zip!/inner.txt"));
+        assert!(!paths(&extracted).contains(&"inner.zip!/deep.zip!/secret.txt"));
+        assert_eq!(extracted.skipped.len(), 1);
+        assert_eq!(extracted.skipped[0].0, "inner.zip!/deep.zip");
+    }
+
+    #[test]
+    fn fails_with_too_many_entries() {
+        let data = zip(&[
+            ("1", b"1"),
+            ("2", b"2"),
+            ("3", b"3"),
+            ("4", b"4"),
+            ("5", b"5"),
+        ]);
+        let limits = ArchiveLimits {
+            max_entries: 3,
+            ..ArchiveLimits::default()
+        };
+
+        assert!(extract("a.zip", &data, &limits).is_err());
+    }
+
+    #[test]
+    fn fails_over_the_total_size() {
+        let member = vec![b'a'; 600];
+        let data = zip(&[("1.txt", &member), ("2.txt", &member)]);
+        let limits = ArchiveLimits {
+            max_total_size: 1000,
+            ..ArchiveLimits::default()
+        };
+
+        assert!(extract("a.zip", &data, &limits).is_err());
+    }
+
+    #[test]
+    fn counts_tar_gz_members_once() {
+        let member: Vec<u8> = (0..600).map(|i| (i * 7 % 256) as u8).collect();
+        let data = tar_gz(&[("data.bin", &member)]);
+        let limits = ArchiveLimits {
+            max_total_size: 1000,
+            ..ArchiveLimits::default()
+        };
+
+        let extracted = extract("a.tar.gz", &data, &limits).unwrap();
+        assert_eq!(paths(&extracted), vec!["data.bin"]);
+        assert_eq!(extracted.entries[0].data, member);
+    }
+}
@@ -0,0 +1,191 @@
+use serde_json::{self, Value};
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use PatternMatch;
+
+const VERSION: u64 = 1;
+
+//
+// A finding that's already been triaged. Entries read from grouped reports don't have a file or
+// commit, and match the secret wherever it's found.
+//
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+struct Entry {
+    rule_id: String,
+    fingerprint: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    file: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    commit: Option<String>,
+}
+
+impl Entry {
+    fn new(pattern_match: &PatternMatch) -> Entry {
+        Entry {
+            rule_id: pattern_match.rule_id.clone(),
+            fingerprint: pattern_match.fingerprint.clone(),
+            file: Some(pattern_match.file.clone()),
+            commit: Some(pattern_match.commit_hash.clone()),
+        }
+    }
+
+    // Read a finding from a baseline or a json/jsonl report, which call the commit
+    // `commit_hash`
+    fn from_json(value: &Value) -> Option<Entry> {
+        let field = |name: &str| value.get(name).and_then(Value::as_str).map(str::to_owned);
+
+        Some(Entry {
+            rule_id: field("rule_id")?,
+            fingerprint: field("fingerprint")?,
+            file: field("file"),
+            commit: field("commit").or_else(|| field("commit_hash")),
+        })
+    }
+}
+
+/// Findings that have already been seen, so only new ones are reported
+#[derive(Default)]
+pub struct Baseline {
+    entries: BTreeSet<Entry>,
+}
+
+impl Baseline {
+    //
+    // Load a baseline written by --write-baseline, or the findings in a report written with
+    // --format json or jsonl
+    //
+    pub fn load(path: &str) -> Result<Baseline, String> {
+        let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let values: Vec<Value> = match serde_json::from_str::<Value>(&contents) {
+            Ok(Value::Object(mut document)) => match document.remove("findings") {
+                Some(Value::Array(findings)) => findings,
+                _ => return Err("expected a findings list".to_owned()),
+            },
+            Ok(_) => return Err("expected a baseline or a json report".to_owned()),
+            // JSON Lines doesn't parse as a single value
+            Err(_) => contents
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .enumerate()
+                .map(|(i, line)| {
+                    serde_json::from_str(line).map_err(|e| format!("line {}: {}", i + 1, e))
+                })
+                .collect::<Result<_, _>>()?,
+        };
+
+        let mut entries = BTreeSet::new();
+        for (i, value) in values.iter().enumerate() {
+            match Entry::from_json(value) {
+                Some(entry) => entries.insert(entry),
+                None => return Err(format!("finding {} has no rule_id or fingerprint", i + 1)),
+            };
+        }
+
+        Ok(Baseline { entries })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn contains(&self, pattern_match: &PatternMatch) -> bool {
+        let entry = Entry::new(pattern_match);
+        let files = [entry.file.clone(), None];
+        let commits = [entry.commit.clone(), None];
+
+        files.iter().any(|file| {
+            commits.iter().any(|commit| {
+                self.entries.contains(&Entry {
+                    file: file.clone(),
+                    commit: commit.clone(),
+                    ..entry.clone()
+                })
+            })
+        })
+    }
+
+    pub fn insert(&mut self, pattern_match: &PatternMatch) {
+        self.entries.insert(Entry::new(pattern_match));
+    }
+
+    /// Write the baseline sorted, so it can be kept in version control and diffed
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let findings: Vec<&Entry> = self.entries.iter().collect();
+        let mut writer = BufWriter::new(File::create(path).map_err(|e| e.to_string())?);
+        serde_json::to_writer_pretty(
+            &mut writer,
+            &json!({ "version": VERSION, "findings": findings }),
+        )
+        .map_err(|e| e.to_string())?;
+        writer.write_all(b"\n").map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    fn load(name: &str, contents: &str) -> Result<Baseline, String> {
+        let path = env::temp_dir().join(format!("baseline-{}-{}", process::id(), name));
+        fs::write(&path, contents).unwrap();
+        let baseline = Baseline::load(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        baseline
+    }
+
+    fn entry(rule_id: &str, fingerprint: &str, file: Option<&str>, commit: Option<&str>) -> Entry {
+        Entry {
+            rule_id: rule_id.to_owned(),
+            fingerprint: fingerprint.to_owned(),
+            file: file.map(str::to_owned),
+            commit: commit.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn loads_a_json_report() {
+        let baseline = load(
+            "report.json",
+            r#"{"findings": [
+                {"rule_id": "aws-key", "fingerprint": "ab", "file": "a.py", "commit_hash": "c1"},
+                {"rule_id": "github-token", "fingerprint": "cd", "paths": ["b.py"]}
+            ], "scan": {"findings": 2}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(baseline.len(), 2);
+        assert!(baseline
+            .entries
+            .contains(&entry("aws-key", "ab", Some("a.py"), Some("c1"))));
+        // grouped findings have no file or commit, and match anywhere
+        assert!(baseline
+            .entries
+            .contains(&entry("github-token", "cd", None, None)));
+    }
+
+    #[test]
+    fn loads_a_jsonl_report() {
+        let baseline = load(
+            "report.jsonl",
+            "{\"rule_id\": \"aws-key\", \"fingerprint\": \"ab\", \"file\": \"a.py\", \"commit_hash\": \"c1\"}\n\
+             \n\
+             {\"rule_id\": \"aws-key\", \"fingerprint\": \"ab\", \"file\": \"b.py\", \"commit\": \"c2\"}\n",
+        )
+        .unwrap();
+
+        assert_eq!(baseline.len(), 2);
+        assert!(baseline
+            .entries
+            .contains(&entry("aws-key", "ab", Some("b.py"), Some("c2"))));
+    }
+
+    #[test]
+    fn rejects_findings_without_a_fingerprint() {
+        assert!(load("missing.json", r#"{"findings": [{"rule_id": "aws-key"}]}"#).is_err());
+        assert!(load("list.json", "[]").is_err());
+        assert!(load("broken.jsonl", "{\"rule_id\": \n").is_err());
+    }
+}
@@ -0,0 +1,78 @@
+/// Number of leading bytes inspected when deciding if a blob is binary (same as git)
+const SNIFF_LENGTH: usize = 8000;
+
+/// Shortest run of printable characters that will be extracted from a binary blob
+pub const MIN_STRING_LENGTH: usize = 6;
+
+//
+// Determine if a blob is binary the same way git does -- by looking for a NUL byte near the
+// start of the blob
+//
+pub fn is_binary(data: &[u8]) -> bool {
+    data.iter().take(SNIFF_LENGTH).any(|b| *b == 0)
+}
+
+fn is_printable(b: u8) -> bool {
+    b == b'\t' || (0x20..0x7f).contains(&b)
+}
+
+//
+// Pull out runs of printable ASCII and UTF-16LE text from a binary blob, similar to running
+// both `strings` and `strings -el`
+//
+pub fn extract_strings(data: &[u8], min_length: usize) -> Vec<String> {
+    let mut strings = extract_ascii(data, min_length);
+    strings.extend(extract_utf16le(data, min_length));
+
+    strings
+}
+
+fn extract_ascii(data: &[u8], min_length: usize) -> Vec<String> {
+    let mut strings = Vec::new();
+    let mut current = String::new();
+
+    for b in data {
+        if is_printable(*b) {
+            current.push(*b as char);
+            continue;
+        }
+
+        if current.len() >= min_length {
+            strings.push(current.clone());
+        }
+        current.clear();
+    }
+
+    if current.len() >= min_length {
+        strings.push(current);
+    }
+
+    strings
+}
+
+fn extract_utf16le(data: &[u8], min_length: usize) -> Vec<String> {
+    let mut strings = Vec::new();
+
+    // strings can start on either an even or odd offset
+    for start in 0..2 {
+        let mut current = String::new();
+
+        for pair in data[start.min(data.len())..].chunks(2) {
+            if pair.len() == 2 && pair[1] == 0 && is_printable(pair[0]) {
+                current.push(pair[0] as char);
+                continue;
+            }
+
+            if current.len() >= min_length {
+                strings.push(current.clone());
+            }
+            current.clear();
+        }
+
+        if current.len() >= min_length {
+            strings.push(current);
+        }
+    }
+
+    strings
+}
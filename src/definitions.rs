@@ -0,0 +1,921 @@
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use import;
+use regex::Regex;
+use signatures;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use toml;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Config {
+    /// Other definitions files, or directories of them, that this file builds on
+    pub include: Option<Vec<String>>,
+    pub patterns: Option<Vec<Pattern>>,
+    pub filters: Option<Vec<Filter>>,
+    pub files: Option<Vec<FilePattern>>,
+    pub keys: Option<Vec<KeyPattern>>,
+    pub allowlist: Option<Allowlist>,
+    pub overrides: Option<Vec<Override>>,
+
+    /// Parts of imported rule files that couldn't be carried over
+    #[serde(skip_deserializing, skip_serializing)]
+    pub warnings: Vec<String>,
+}
+
+impl Config {
+    //
+    // Layer the rules from another definitions file on top of this one. Rules in `other`
+    // replace any rules here with the same id.
+    //
+    pub fn merge(&mut self, other: Config) {
+        fn append<T>(rules: &mut Option<Vec<T>>, other: Option<Vec<T>>) {
+            if let Some(other) = other {
+                rules.get_or_insert_with(Vec::new).extend(other);
+            }
+        }
+
+        fn remove_ids<T: Rule>(rules: &mut Option<Vec<T>>, ids: &HashSet<String>) {
+            if let Some(rules) = rules.as_mut() {
+                rules.retain(|rule| rule.id().is_none_or(|id| !ids.contains(id)));
+            }
+        }
+
+        let mut ids = HashSet::new();
+        ids.extend(other.patterns.iter().flatten().filter_map(|p| p.id.clone()));
+        ids.extend(other.files.iter().flatten().filter_map(|f| f.id.clone()));
+        ids.extend(other.keys.iter().flatten().filter_map(|k| k.id.clone()));
+        remove_ids(&mut self.patterns, &ids);
+        remove_ids(&mut self.files, &ids);
+        remove_ids(&mut self.keys, &ids);
+
+        append(&mut self.patterns, other.patterns);
+        append(&mut self.filters, other.filters);
+        append(&mut self.files, other.files);
+        append(&mut self.keys, other.keys);
+        append(&mut self.overrides, other.overrides);
+        self.warnings.extend(other.warnings);
+
+        if let Some(other) = other.allowlist {
+            let allowlist = self.allowlist.get_or_insert_with(Allowlist::default);
+            append(&mut allowlist.paths, other.paths);
+        }
+    }
+
+    //
+    // Apply each override to the rule it names. Returns a message for each override that
+    // doesn't match any rule.
+    //
+    pub fn apply_overrides(&mut self) -> Vec<String> {
+        fn apply(enabled: &mut Option<bool>, severity: &mut Option<Severity>, o: &Override) {
+            if o.enabled.is_some() {
+                *enabled = o.enabled;
+            }
+            if o.severity.is_some() {
+                *severity = o.severity;
+            }
+        }
+
+        let mut errors = Vec::new();
+
+        for o in self.overrides.take().unwrap_or_default() {
+            let mut found = false;
+
+            for pattern in self.patterns.iter_mut().flatten() {
+                if pattern.rule_id() == o.id {
+                    apply(&mut pattern.enabled, &mut pattern.severity, &o);
+                    found = true;
+                }
+            }
+            for file in self.files.iter_mut().flatten() {
+                if file.rule_id() == o.id {
+                    apply(&mut file.enabled, &mut file.severity, &o);
+                    found = true;
+                }
+            }
+            for key in self.keys.iter_mut().flatten() {
+                if key.rule_id() == o.id {
+                    apply(&mut key.enabled, &mut key.severity, &o);
+                    found = true;
+                }
+            }
+
+            if !found {
+                errors.push(format!("Override for {} doesn't match any rule", o.id));
+                continue;
+            }
+
+            for pattern in o.filters.iter().flatten() {
+                self.filters.get_or_insert_with(Vec::new).push(Filter {
+                    description: format!("Override for {}", o.id),
+                    pattern: pattern.clone(),
+                    rules: Some(vec![o.id.clone()]),
+                    ..Default::default()
+                });
+            }
+        }
+
+        errors
+    }
+}
+
+//
+// Read a definitions file along with everything it includes. Included files are layered in
+// order beneath the file that includes them, and directories include every `.toml` file in
+// them sorted by name.
+//
+pub fn load_file(path: &Path) -> Result<Config, String> {
+    load_file_inner(path, &mut Vec::new())
+}
+
+fn load_file_inner(path: &Path, including: &mut Vec<PathBuf>) -> Result<Config, String> {
+    let canonical = path
+        .canonicalize()
+        .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+    if including.contains(&canonical) {
+        return Err(format!("{} includes itself", path.display()));
+    }
+
+    let mut contents = String::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut contents))
+        .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+    let mut config = parse_file(path, &contents)?;
+
+    let includes = match config.include.take() {
+        Some(includes) => includes,
+        None => return Ok(config),
+    };
+
+    including.push(canonical);
+    let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let mut layered = Config::default();
+    for include in includes {
+        let include = base_dir.join(include);
+        let paths = if include.is_dir() {
+            let mut paths: Vec<PathBuf> = fs::read_dir(&include)
+                .map_err(|e| format!("could not read {}: {}", include.display(), e))?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|p| p.extension().is_some_and(|ext| ext == "toml"))
+                .collect();
+            paths.sort();
+            paths
+        } else {
+            vec![include]
+        };
+
+        for path in paths {
+            layered.merge(load_file_inner(&path, including)?);
+        }
+    }
+    including.pop();
+
+    layered.merge(config);
+    Ok(layered)
+}
+
+//
+// Parse a single definitions file, converting it first if it's another scanner's rules
+//
+pub fn parse_file(path: &Path, contents: &str) -> Result<Config, String> {
+    match import::detect(path, contents) {
+        Some(format) => {
+            let mut config = import::import(format, contents)
+                .map_err(|e| format!("error importing {}: {}", path.display(), e))?;
+            for warning in config.warnings.iter_mut() {
+                *warning = format!("{}: {}", path.display(), warning);
+            }
+            Ok(config)
+        }
+        None => {
+            toml::from_str(contents).map_err(|e| format!("error parsing {}: {}", path.display(), e))
+        }
+    }
+}
+
+/// Changes to a rule defined elsewhere, e.g. in a built-in pack or an included file
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct Override {
+    /// Id of the rule to change, or its description if it doesn't have one
+    pub id: String,
+    pub enabled: Option<bool>,
+    pub severity: Option<Severity>,
+    /// Extra filter patterns for matches of this rule
+    pub filters: Option<Vec<String>>,
+}
+
+/// Paths that are never scanned by any rule, e.g. vendored code or test fixtures
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct Allowlist {
+    pub paths: Option<Vec<String>>,
+
+    #[serde(skip_deserializing, skip_serializing)]
+    pub path_globs: Option<GlobSet>,
+}
+
+impl Allowlist {
+    pub fn is_allowed(&self, path: &str) -> bool {
+        self.path_globs
+            .as_ref()
+            .is_some_and(|globs| globs.is_match(path))
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct Pattern {
+    /// Stable name used to refer to this rule, defaults to the description
+    pub id: Option<String>,
+    pub description: String,
+    pub severity: Option<Severity>,
+    pub tags: Option<Vec<String>>,
+    /// How to fix a finding, e.g. which credentials to rotate
+    pub remediation: Option<String>,
+    pub pattern: String,
+    pub enabled: Option<bool>,
+    pub case_sensitive: Option<bool>,
+    /// Globs for the only paths this pattern is checked against, e.g. `**/*.config`
+    pub paths: Option<Vec<String>>,
+    /// Globs for paths this pattern is never checked against, e.g. `tests/**`
+    pub exclude_paths: Option<Vec<String>>,
+    /// Capture group holding the secret, defaults to the whole match
+    pub secret_group: Option<usize>,
+    /// Lowest Shannon entropy, in bits per character, a secret needs to be reported
+    pub min_entropy: Option<f64>,
+    /// Words, any of which must be on the line (ignoring case) for the pattern to be checked
+    pub keywords: Option<Vec<String>>,
+    /// Lines this pattern must match, checked by `test-definitions`
+    pub should_match: Option<Vec<String>>,
+    /// Lines this pattern must not match or that a filter must drop, checked by
+    /// `test-definitions`
+    pub should_not_match: Option<Vec<String>>,
+
+    #[serde(skip_deserializing, skip_serializing)]
+    pub regex: Option<Regex>,
+    #[serde(skip_deserializing, skip_serializing)]
+    pub path_globs: Option<GlobSet>,
+    #[serde(skip_deserializing, skip_serializing)]
+    pub exclude_globs: Option<GlobSet>,
+}
+
+impl Pattern {
+    /// Whether this pattern should be checked against the file at `path`
+    pub fn applies_to(&self, path: &str) -> bool {
+        if let Some(globs) = self.path_globs.as_ref() {
+            if !globs.is_match(path) {
+                return false;
+            }
+        }
+
+        if let Some(globs) = self.exclude_globs.as_ref() {
+            if globs.is_match(path) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// A rule matched against the path, and optionally the contents, of each changed file. Every
+/// criteria that's set must match.
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct FilePattern {
+    pub id: Option<String>,
+    pub description: String,
+    pub severity: Option<Severity>,
+    pub tags: Option<Vec<String>>,
+    /// How to fix a finding, e.g. which credentials to rotate
+    pub remediation: Option<String>,
+    /// File extension without the leading `.`, e.g. `pfx`
+    pub extension: Option<String>,
+    /// Glob matched against the file's name, e.g. `id_rsa` or `*.kdbx`
+    pub filename: Option<String>,
+    /// Globs matched against the file's full path, e.g. `**/secrets/**`
+    pub paths: Option<Vec<String>>,
+    /// Regex matched against the file's full path
+    pub regex: Option<String>,
+    /// Globs for paths that should never match this rule
+    pub exclude_paths: Option<Vec<String>>,
+    /// Names of built-in content signatures, any of which may match, e.g. `pkcs12` or `sqlite`
+    pub signatures: Option<Vec<String>>,
+    /// Hex encoded bytes the file must start with, e.g. `FEEDFEED`
+    pub magic: Option<String>,
+    pub binary: Option<bool>,
+    pub enabled: Option<bool>,
+
+    #[serde(skip_deserializing, skip_serializing)]
+    pub filename_glob: Option<GlobSet>,
+    #[serde(skip_deserializing, skip_serializing)]
+    pub path_globs: Option<GlobSet>,
+    #[serde(skip_deserializing, skip_serializing)]
+    pub path_regex: Option<Regex>,
+    #[serde(skip_deserializing, skip_serializing)]
+    pub exclude_globs: Option<GlobSet>,
+    #[serde(skip_deserializing, skip_serializing)]
+    pub magic_bytes: Option<Vec<u8>>,
+}
+
+impl FilePattern {
+    /// Whether this rule needs the file's contents to decide if it matches
+    pub fn needs_content(&self) -> bool {
+        self.signatures.is_some() || self.magic.is_some()
+    }
+
+    //
+    // Check a file against this rule. Rules that need the file's contents never match if
+    // `data` isn't provided.
+    //
+    pub fn is_match(&self, path: &str, data: Option<&[u8]>) -> bool {
+        if !self.matches_path(path) {
+            return false;
+        }
+
+        if !self.needs_content() {
+            return true;
+        }
+
+        match data {
+            Some(data) => self.matches_content(data),
+            None => false,
+        }
+    }
+
+    fn matches_content(&self, data: &[u8]) -> bool {
+        let matches_signature = self
+            .signatures
+            .iter()
+            .flatten()
+            .any(|name| signatures::matches(name, data));
+        let matches_magic = self
+            .magic_bytes
+            .as_ref()
+            .is_some_and(|magic| data.starts_with(magic));
+
+        matches_signature || matches_magic
+    }
+
+    pub fn matches_path(&self, path: &str) -> bool {
+        if !self.enabled.unwrap_or(true) {
+            return false;
+        }
+
+        let file_name = path.rsplit('/').next().unwrap();
+
+        if let Some(extension) = self.extension.as_ref() {
+            let matches_extension = file_name.len() > extension.len()
+                && file_name.ends_with(extension.as_str())
+                && file_name[..file_name.len() - extension.len()].ends_with('.');
+            if !matches_extension {
+                return false;
+            }
+        }
+
+        if let Some(glob) = self.filename_glob.as_ref() {
+            if !glob.is_match(file_name) {
+                return false;
+            }
+        }
+
+        if let Some(globs) = self.path_globs.as_ref() {
+            if !globs.is_match(path) {
+                return false;
+            }
+        }
+
+        if let Some(regex) = self.path_regex.as_ref() {
+            if !regex.is_match(path) {
+                return false;
+            }
+        }
+
+        if let Some(globs) = self.exclude_globs.as_ref() {
+            if globs.is_match(path) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// A rule matched against the keys and values of structured files (JSON, YAML, XML, etc.)
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct KeyPattern {
+    pub id: Option<String>,
+    pub description: String,
+    pub severity: Option<Severity>,
+    pub tags: Option<Vec<String>>,
+    /// How to fix a finding, e.g. which credentials to rotate
+    pub remediation: Option<String>,
+    /// Regex the key (or attribute) name must match
+    pub key: String,
+    /// Regex the value must match -- any non-empty value is matched if this isn't set
+    pub value: Option<String>,
+    /// Report values that look like placeholders such as `${DB_PASSWORD}` or `changeme`
+    pub allow_placeholders: Option<bool>,
+    pub enabled: Option<bool>,
+    pub case_sensitive: Option<bool>,
+
+    #[serde(skip_deserializing, skip_serializing)]
+    pub key_regex: Option<Regex>,
+    #[serde(skip_deserializing, skip_serializing)]
+    pub value_regex: Option<Regex>,
+}
+
+#[derive(Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Low,
+    #[default]
+    Medium,
+    High,
+    Critical,
+}
+
+impl FromStr for Severity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Severity, String> {
+        match s.to_lowercase().as_str() {
+            "info" => Ok(Severity::Info),
+            "low" => Ok(Severity::Low),
+            "medium" => Ok(Severity::Medium),
+            "high" => Ok(Severity::High),
+            "critical" => Ok(Severity::Critical),
+            _ => Err(format!(
+                "unknown severity {} (expected info, low, medium, high or critical)",
+                s
+            )),
+        }
+    }
+}
+
+/// Metadata shared by every kind of rule
+pub trait Rule {
+    /// The id set in the definitions file, if any
+    fn id(&self) -> Option<&str>;
+    fn description(&self) -> &str;
+    fn severity(&self) -> Severity;
+    fn tags(&self) -> &[String];
+    fn remediation(&self) -> Option<&str>;
+
+    /// The rule's id, falling back to its description for older definitions
+    fn rule_id(&self) -> &str {
+        self.id().unwrap_or_else(|| self.description())
+    }
+}
+
+impl Rule for Pattern {
+    fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn severity(&self) -> Severity {
+        self.severity.unwrap_or_default()
+    }
+
+    fn tags(&self) -> &[String] {
+        self.tags.as_ref().map_or(&[], |tags| tags.as_slice())
+    }
+
+    fn remediation(&self) -> Option<&str> {
+        self.remediation.as_deref()
+    }
+}
+
+impl Rule for FilePattern {
+    fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn severity(&self) -> Severity {
+        self.severity.unwrap_or_default()
+    }
+
+    fn tags(&self) -> &[String] {
+        self.tags.as_ref().map_or(&[], |tags| tags.as_slice())
+    }
+
+    fn remediation(&self) -> Option<&str> {
+        self.remediation.as_deref()
+    }
+}
+
+impl Rule for KeyPattern {
+    fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn severity(&self) -> Severity {
+        self.severity.unwrap_or_default()
+    }
+
+    fn tags(&self) -> &[String] {
+        self.tags.as_ref().map_or(&[], |tags| tags.as_slice())
+    }
+
+    fn remediation(&self) -> Option<&str> {
+        self.remediation.as_deref()
+    }
+}
+
+//
+// Determine if a rule should be used given the severity and tags selected on the command line.
+// Rules with any of the selected tags are used.
+//
+pub fn is_selected(rule: &dyn Rule, min_severity: Severity, tags: &[String]) -> bool {
+    rule.severity() >= min_severity
+        && (tags.is_empty() || rule.tags().iter().any(|tag| tags.contains(tag)))
+}
+
+//
+// Check that rule ids are unique across every kind of rule. Returns a message for each
+// duplicated id.
+//
+pub fn check_rule_ids(rules: &[&dyn Rule]) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut errors = Vec::new();
+
+    for rule in rules {
+        let id = match rule.id() {
+            Some(id) => id,
+            None => continue,
+        };
+
+        if !seen.insert(id) {
+            errors.push(format!("Rule id {} is used by more than one rule", id));
+        }
+    }
+
+    errors
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Debug)]
+#[serde(rename_all(deserialize = "lowercase"))]
+pub enum MatchType {
+    Pattern,
+    File,
+    Key,
+}
+
+/// Part of a match a filter's pattern is checked against
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterField {
+    /// The matched text -- see `FilterTarget`
+    Text,
+    /// Path of the file the match was found in
+    File,
+    /// Hash of the commit the match was found in
+    Commit,
+    /// Subject line of the commit the match was found in
+    Message,
+    /// `Name <email>` of the commit's author
+    Author,
+}
+
+/// How much of the matched text a `text` filter is checked against
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterTarget {
+    /// What the rule matched through to the end of its line, which is what's in the results
+    Text,
+    /// Only the secret -- the rule's `secret_group`, or everything its regex matched
+    Secret,
+    /// The whole line the match was found on
+    Line,
+}
+
+/// A negative pattern -- matches it hits are dropped from the results
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct Filter {
+    pub description: String,
+    pub pattern: String,
+    pub enabled: Option<bool>,
+    pub case_sensitive: Option<bool>,
+    /// Ids (or descriptions) of the rules this filter applies to, of any match type
+    pub rules: Option<Vec<String>>,
+    /// Kinds of matches this filter applies to when `rules` isn't set, defaults to `pattern`
+    pub match_types: Option<Vec<MatchType>>,
+    /// Fields checked against the pattern, any of which may match. Defaults to `text`.
+    pub fields: Option<Vec<FilterField>>,
+    /// Defaults to `text`
+    pub target: Option<FilterTarget>,
+
+    #[serde(skip_deserializing, skip_serializing)]
+    pub regex: Option<Regex>,
+}
+
+impl Filter {
+    /// Whether this filter should be checked against matches of the given rule
+    pub fn applies_to(&self, rule_id: &str, match_type: MatchType) -> bool {
+        if !self.enabled.unwrap_or(true) {
+            return false;
+        }
+
+        match (self.rules.as_ref(), self.match_types.as_ref()) {
+            (Some(rules), _) => rules.iter().any(|rule| rule == rule_id),
+            (None, Some(match_types)) => match_types.contains(&match_type),
+            (None, None) => match_type == MatchType::Pattern,
+        }
+    }
+
+    pub fn fields(&self) -> &[FilterField] {
+        match self.fields.as_ref() {
+            Some(fields) => fields,
+            None => &[FilterField::Text],
+        }
+    }
+
+    pub fn target(&self) -> FilterTarget {
+        self.target.unwrap_or(FilterTarget::Text)
+    }
+}
+
+/// A problem with one of the rules in a definitions file
+#[derive(Debug)]
+pub struct RuleError {
+    /// Position of the rule within its section of the definitions file
+    pub index: usize,
+    pub message: String,
+}
+
+//
+// Compile regex patterns for a given Pattern struct. Patterns that fail to compile are disabled
+// and an error is returned for each of them.
+//
+pub fn compile_patterns(patterns: &mut [Pattern]) -> Vec<RuleError> {
+    let mut errors = Vec::new();
+
+    for (index, pattern) in patterns.iter_mut().enumerate() {
+        if !pattern.enabled.unwrap_or(true) {
+            continue;
+        }
+
+        let mut messages = Vec::new();
+        pattern.regex = compile_regex(&pattern.pattern, pattern.case_sensitive)
+            .map_err(|e| messages.push(e))
+            .ok();
+        pattern.path_globs = compile_optional_globs(pattern.paths.as_ref(), &mut messages);
+        pattern.exclude_globs =
+            compile_optional_globs(pattern.exclude_paths.as_ref(), &mut messages);
+
+        if let (Some(regex), Some(group)) = (pattern.regex.as_ref(), pattern.secret_group) {
+            if group >= regex.captures_len() {
+                messages.push(format!("secret_group {} doesn't exist in the pattern", group));
+            }
+        }
+
+        // a pattern whose scope couldn't be compiled would otherwise match everywhere
+        disable_on_error(&mut pattern.enabled, index, messages, &mut errors);
+    }
+
+    errors
+}
+
+//
+// Compile the path globs for the global allowlist
+//
+pub fn compile_allowlist(allowlist: &mut Allowlist) -> Result<(), String> {
+    allowlist.path_globs = match allowlist.paths.as_ref() {
+        Some(paths) => Some(compile_globs(paths)?),
+        None => None,
+    };
+
+    Ok(())
+}
+
+//
+// Compile the regexes for a given Filter struct. Filters that fail to compile are disabled.
+//
+pub fn compile_filters(filters: &mut [Filter]) -> Vec<RuleError> {
+    let mut errors = Vec::new();
+
+    for (index, filter) in filters.iter_mut().enumerate() {
+        if !filter.enabled.unwrap_or(true) {
+            continue;
+        }
+
+        let mut messages = Vec::new();
+        filter.regex = compile_regex(&filter.pattern, filter.case_sensitive)
+            .map_err(|e| messages.push(e))
+            .ok();
+        disable_on_error(&mut filter.enabled, index, messages, &mut errors);
+    }
+
+    errors
+}
+
+//
+// Compile the key and value regexes for a given KeyPattern struct. Rules that fail to compile
+// are disabled.
+//
+pub fn compile_key_patterns(patterns: &mut [KeyPattern]) -> Vec<RuleError> {
+    let mut errors = Vec::new();
+
+    for (index, pattern) in patterns.iter_mut().enumerate() {
+        if !pattern.enabled.unwrap_or(true) {
+            continue;
+        }
+
+        let mut messages = Vec::new();
+        pattern.key_regex = compile_regex(&pattern.key, pattern.case_sensitive)
+            .map_err(|e| messages.push(e))
+            .ok();
+        pattern.value_regex = match pattern.value.as_ref() {
+            Some(value) => compile_regex(value, pattern.case_sensitive)
+                .map_err(|e| messages.push(e))
+                .ok(),
+            None => None,
+        };
+
+        // without its value regex the rule would match every value
+        disable_on_error(&mut pattern.enabled, index, messages, &mut errors);
+    }
+
+    errors
+}
+
+//
+// Compile the globs and regexes for a given FilePattern struct. Rules that fail to compile or
+// that don't have anything to match on are disabled.
+//
+pub fn compile_file_patterns(patterns: &mut [FilePattern]) -> Vec<RuleError> {
+    let mut errors = Vec::new();
+
+    for (index, pattern) in patterns.iter_mut().enumerate() {
+        if !pattern.enabled.unwrap_or(true) {
+            continue;
+        }
+
+        let mut messages = Vec::new();
+
+        if pattern.extension.is_none()
+            && pattern.filename.is_none()
+            && pattern.paths.is_none()
+            && pattern.regex.is_none()
+            && !pattern.needs_content()
+        {
+            messages
+                .push("needs an extension, filename, paths, regex, signatures or magic".to_string());
+        }
+
+        // older definitions may have included the leading "."
+        if let Some(extension) = pattern.extension.as_mut() {
+            *extension = extension.trim_start_matches('.').to_string();
+        }
+
+        pattern.filename_glob = compile_optional_globs(
+            pattern.filename.as_ref().map(|f| vec![f.clone()]).as_ref(),
+            &mut messages,
+        );
+        pattern.path_globs = compile_optional_globs(pattern.paths.as_ref(), &mut messages);
+        pattern.path_regex = match pattern.regex.as_ref() {
+            Some(regex) => compile_regex(regex, Some(true))
+                .map_err(|e| messages.push(e))
+                .ok(),
+            None => None,
+        };
+        pattern.exclude_globs =
+            compile_optional_globs(pattern.exclude_paths.as_ref(), &mut messages);
+
+        pattern.magic_bytes = match pattern.magic.as_ref() {
+            Some(magic) => {
+                let bytes = signatures::parse_hex(magic);
+                if bytes.is_none() {
+                    messages.push(format!("could not parse magic bytes {}", magic));
+                }
+                bytes
+            }
+            None => None,
+        };
+
+        for name in pattern.signatures.iter().flatten() {
+            if !signatures::is_known(name) {
+                messages.push(format!(
+                    "unknown signature {} (expected one of: {})",
+                    name,
+                    signatures::SIGNATURE_NAMES.join(", ")
+                ));
+            }
+        }
+
+        disable_on_error(&mut pattern.enabled, index, messages, &mut errors);
+    }
+
+    errors
+}
+
+// Disables a rule if anything went wrong compiling it
+fn disable_on_error(
+    enabled: &mut Option<bool>,
+    index: usize,
+    messages: Vec<String>,
+    errors: &mut Vec<RuleError>,
+) {
+    if messages.is_empty() {
+        return;
+    }
+
+    *enabled = Some(false);
+    errors.extend(
+        messages
+            .into_iter()
+            .map(|message| RuleError { index, message }),
+    );
+}
+
+fn compile_optional_globs(
+    globs: Option<&Vec<String>>,
+    messages: &mut Vec<String>,
+) -> Option<GlobSet> {
+    globs.and_then(|globs| compile_globs(globs).map_err(|e| messages.push(e)).ok())
+}
+
+//
+// Compile a list of path globs into a single set. `*` does not match across directories, but
+// `**` does.
+//
+pub fn compile_globs(globs: &[String]) -> Result<GlobSet, String> {
+    let mut builder = GlobSetBuilder::new();
+
+    for glob in globs {
+        let compiled = GlobBuilder::new(glob)
+            .literal_separator(true)
+            .build()
+            .map_err(|e| format!("could not compile glob {}: {}", glob, e))?;
+        builder.add(compiled);
+    }
+
+    builder
+        .build()
+        .map_err(|e| format!("could not compile globs: {}", e))
+}
+
+pub fn compile_regex(pattern: &str, case_sensitive: Option<bool>) -> Result<Regex, String> {
+    let pattern = if case_sensitive.unwrap_or(false) {
+        pattern.to_owned()
+    } else {
+        "(?i)".to_owned() + pattern
+    };
+
+    Regex::new(&pattern).map_err(|e| format!("could not compile pattern: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use packs;
+
+    #[test]
+    fn filters_check_the_matched_text_unless_told_otherwise() {
+        let contents = "\
+[[filters]]
+description = \"Samples\"
+pattern = \"sample\"
+
+[[filters]]
+description = \"Test lines\"
+pattern = \"// test$\"
+target = \"line\"
+";
+        let filters = parse_file(Path::new("definitions.toml"), contents)
+            .unwrap()
+            .filters
+            .unwrap();
+
+        assert_eq!(filters[0].target(), FilterTarget::Text);
+        assert_eq!(filters[1].target(), FilterTarget::Line);
+    }
+
+    #[test]
+    fn generic_type_filter_ignores_case_but_not_account_keys() {
+        let contents = packs::get("dotnet").unwrap();
+        let mut filters = parse_file(Path::new("dotnet.toml"), contents)
+            .unwrap()
+            .filters
+            .unwrap();
+        assert!(compile_filters(&mut filters).is_empty());
+        let filter = filters.iter().find(|f| f.pattern.contains("TKey")).unwrap();
+        let regex = filter.regex.as_ref().unwrap();
+
+        assert!(regex.is_match("Dictionary<TKey, TValue>"));
+        assert!(regex.is_match("IDictionary<tkey, string>"));
+        assert!(!regex.is_match("AccountKey=c2VjcmV0=="));
+    }
+}
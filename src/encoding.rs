@@ -0,0 +1,102 @@
+use binary;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+
+/// Number of leading bytes sampled when guessing if a blob without a BOM is UTF-16
+const UTF16_SAMPLE_LENGTH: usize = 1024;
+
+//
+// Decode a blob into text, detecting its encoding from a BOM or by guessing. Returns None if
+// the blob doesn't look like text in any encoding we know about.
+//
+pub fn decode(data: &[u8]) -> Option<String> {
+    let encoding = match Encoding::for_bom(data) {
+        Some((encoding, _)) => encoding,
+        None => guess_utf16(data).unwrap_or(UTF_8),
+    };
+
+    if encoding == UTF_8 && binary::is_binary(data) {
+        return None;
+    }
+
+    // decode() strips any BOM for us
+    let (text, _, had_errors) = encoding.decode(data);
+    if !had_errors {
+        return Some(text.into_owned());
+    }
+
+    if encoding == UTF_8 {
+        // not valid UTF-8 -- legacy files are almost always Windows-1252
+        let (text, _, _) = WINDOWS_1252.decode(data);
+        return Some(text.into_owned());
+    }
+
+    None
+}
+
+//
+// Decode a single line of text that's most likely UTF-8, falling back to Windows-1252 instead
+// of mangling characters into U+FFFD
+//
+pub fn decode_line(line: &[u8]) -> String {
+    match ::std::str::from_utf8(line) {
+        Ok(line) => line.to_string(),
+        Err(_) => WINDOWS_1252.decode(line).0.into_owned(),
+    }
+}
+
+// UTF-16 text without a BOM is mostly ASCII with every other byte set to zero
+fn guess_utf16(data: &[u8]) -> Option<&'static Encoding> {
+    let sample = &data[..data.len().min(UTF16_SAMPLE_LENGTH)];
+    let pairs = sample.len() / 2;
+    if pairs < 2 {
+        return None;
+    }
+
+    let even_zeros = sample.iter().step_by(2).filter(|b| **b == 0).count();
+    let odd_zeros = sample.iter().skip(1).step_by(2).filter(|b| **b == 0).count();
+
+    if odd_zeros * 10 >= pairs * 4 && even_zeros * 20 < pairs {
+        Some(UTF_16LE)
+    } else if even_zeros * 10 >= pairs * 4 && odd_zeros * 20 < pairs {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_line_keeps_utf8() {
+        assert_eq!(
+            decode_line("pässword=hunter2".as_bytes()),
+            "pässword=hunter2"
+        );
+        assert_eq!(decode_line(b""), "");
+    }
+
+    #[test]
+    fn decode_line_falls_back_to_windows_1252() {
+        // "café €5" in Windows-1252
+        assert_eq!(decode_line(b"caf\xe9 \x805"), "café €5");
+    }
+
+    #[test]
+    fn decodes_utf16_with_and_without_bom() {
+        let mut le = vec![0xff, 0xfe];
+        le.extend(
+            "key=value"
+                .encode_utf16()
+                .flat_map(|c| c.to_le_bytes().to_vec()),
+        );
+        assert_eq!(decode(&le).as_deref(), Some("key=value"));
+
+        let be: Vec<u8> = "key=value"
+            .encode_utf16()
+            .flat_map(|c| c.to_be_bytes().to_vec())
+            .collect();
+        assert_eq!(decode(&be).as_deref(), Some("key=value"));
+    }
+}
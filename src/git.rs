@@ -1,8 +1,8 @@
-use std::collections::VecDeque;
+use encoding;
 use std::process::{Command, Output};
 use std::sync::Arc;
 
-#[derive(Clone, Serialize)]
+#[derive(Clone, Copy, Serialize, PartialEq, Debug)]
 pub enum ChangeType {
     Addition,
     Removal,
@@ -12,11 +12,64 @@ pub enum ChangeType {
 #[derive(Clone, Serialize)]
 pub struct Commit {
     pub hash: String,
+    /// The first parent, which the commit is diffed against. Root commits don't have one.
+    #[serde(skip_serializing)]
+    pub parent: Option<String>,
     pub date: String,
+    /// `Name <email>` of the commit's author
+    pub author: String,
+    /// First line of the commit message
+    pub subject: String,
     #[serde(skip_serializing)]
     pub client: Option<Arc<GitClient>>,
 }
 
+/// A single file's section of a commit diff
+pub struct FileDiff {
+    pub file_name: String,
+    /// Blob hash of the file before the commit, if it existed
+    pub old_blob: Option<String>,
+    /// Blob hash of the file after the commit, if it still exists
+    pub new_blob: Option<String>,
+    pub binary: bool,
+    pub lines: Vec<DiffLine>,
+}
+
+/// A line a commit added or removed
+pub struct DiffLine {
+    pub change_type: ChangeType,
+    /// 1-based line number in the new file for additions, or in the old file for removals
+    pub number: usize,
+    /// 1-based column, in characters, that `text` starts at in the file
+    pub column: usize,
+    /// The line with its leading `+`/`-` and surrounding whitespace stripped
+    pub text: String,
+    /// The line before this one in the same version of the file, if the diff includes it
+    pub previous: Option<String>,
+}
+
+/// A file in a commit's tree
+pub struct TreeFile {
+    pub blob: String,
+    pub path: String,
+}
+
+impl FileDiff {
+    fn new(file_name: String) -> FileDiff {
+        FileDiff {
+            file_name,
+            old_blob: None,
+            new_blob: None,
+            binary: false,
+            lines: Vec::new(),
+        }
+    }
+
+    pub fn has_index(&self) -> bool {
+        self.old_blob.is_some() || self.new_blob.is_some()
+    }
+}
+
 pub struct GitClient {
     pub repo_path: String,
 }
@@ -29,7 +82,8 @@ impl GitClient {
     pub fn get_commits(&self, since_date: Option<&str>, until_date: Option<&str>) -> Vec<Commit> {
         let mut args: Vec<String> = vec![
             "log".to_string(),
-            "--format=%H %aI".to_string(),
+            // fields are NUL separated since names and subjects can contain spaces
+            "--format=%H%x00%P%x00%aI%x00%an <%ae>%x00%s".to_string(),
             "--branches=*".to_string(),
         ];
 
@@ -44,29 +98,51 @@ impl GitClient {
 
         let result = self.exec(args.as_slice());
 
-        str::lines(&String::from_utf8(result.stdout).unwrap())
+        str::lines(&String::from_utf8_lossy(&result.stdout))
             .map(|l| {
-                let mut parts = l.split_whitespace();
+                let mut parts = l.split('\0');
                 Commit {
                     hash: parts.next().unwrap().to_string(),
+                    parent: parts
+                        .next()
+                        .and_then(|parents| parents.split(' ').next())
+                        .filter(|parent| !parent.is_empty())
+                        .map(str::to_string),
                     date: parts.next().unwrap().to_string(),
+                    author: parts.next().unwrap_or_default().to_string(),
+                    subject: parts.next().unwrap_or_default().to_string(),
                     client: None,
                 }
-            }).collect::<Vec<Commit>>()
+            })
+            .collect::<Vec<Commit>>()
     }
 
     pub fn get_commit_content(&self, commit: &Commit) -> String {
         let args = vec![
             "diff".to_string(),
-            "-U0".to_string(),
+            // one line of context so the line before each change is known, for markers like
+            // `sourcesecrets:allow` that are put above what they apply to
+            "-U1".to_string(),
+            "--full-index".to_string(),
             format!("{}^!", commit.hash),
         ];
-        String::from_utf8_lossy(&self.exec(&args).stdout).into_owned()
+
+        // decode line by line since files in the same diff may use different encodings
+        self.exec(&args)
+            .stdout
+            .split(|b| *b == b'\n')
+            .map(encoding::decode_line)
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    pub fn get_commit_diff(&self, commit: &Commit) -> Vec<FileDiff> {
+        parse_diff(&self.get_commit_content(commit))
     }
 
     pub fn get_file_at_commit(&self, commit: &str, filename: Option<&String>) -> Vec<u8> {
         let commit = match filename {
-            Some(path) => format!("{}:{}", commit.to_string(), path),
+            Some(path) => format!("{}:{}", commit, path),
             None => commit.to_string(),
         };
         let args = vec!["show".to_string(), commit];
@@ -78,15 +154,87 @@ impl GitClient {
         output.stdout
     }
 
-    pub fn get_file_names_for_commit(&self, commit: &Commit) -> VecDeque<String> {
+    /// Full hash of the commit a revision like `HEAD` points to, if it exists
+    pub fn resolve(&self, revision: &str) -> Option<String> {
         let args = vec![
-            "diff".to_string(),
-            "--name-only".to_string(),
-            commit.hash.clone(),
+            "rev-parse".to_string(),
+            "--verify".to_string(),
+            "--quiet".to_string(),
+            format!("{}^{{commit}}", revision),
         ];
         let output = self.exec(&args);
-        str::lines(&String::from_utf8_lossy(&output.stdout).into_owned())
-            .map(|line| line.to_string())
+        let hash = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+        if output.status.success() && !hash.is_empty() {
+            Some(hash)
+        } else {
+            None
+        }
+    }
+
+    /// Trees of the commits history starts at, which stay the same unless history is rewritten
+    pub fn get_root_trees(&self) -> Vec<String> {
+        let args = vec![
+            "log".to_string(),
+            "--max-parents=0".to_string(),
+            "--format=%T".to_string(),
+            "--branches=*".to_string(),
+        ];
+        let output = self.exec(&args);
+
+        let mut trees: Vec<String> = str::lines(&String::from_utf8_lossy(&output.stdout))
+            .map(str::to_string)
+            .collect();
+        trees.sort();
+        trees.dedup();
+        trees
+    }
+
+    /// Name and commit hash of every local branch, the same branches history is read from
+    pub fn get_branches(&self) -> Vec<(String, String)> {
+        let args = vec![
+            "for-each-ref".to_string(),
+            "--format=%(refname:short)%00%(objectname)".to_string(),
+            "refs/heads".to_string(),
+        ];
+        let output = self.exec(&args);
+
+        str::lines(&String::from_utf8_lossy(&output.stdout))
+            .filter_map(|line| {
+                let mut parts = line.split('\0');
+                Some((parts.next()?.to_string(), parts.next()?.to_string()))
+            })
+            .collect()
+    }
+
+    /// Every file in a commit's tree, not including submodules
+    pub fn get_tree(&self, commit: &str) -> Vec<TreeFile> {
+        let args = vec![
+            "ls-tree".to_string(),
+            "-r".to_string(),
+            "-z".to_string(),
+            "--full-tree".to_string(),
+            commit.to_string(),
+        ];
+        let output = self.exec(&args);
+
+        // entries look like `<mode> <type> <hash>\t<path>`, and are NUL separated so paths
+        // don't need unquoting
+        String::from_utf8_lossy(&output.stdout)
+            .split('\0')
+            .filter_map(|entry| {
+                let mut parts = entry.splitn(2, '\t');
+                let mut info = parts.next()?.split(' ');
+                let path = parts.next()?;
+                if info.nth(1)? != "blob" {
+                    return None;
+                }
+
+                Some(TreeFile {
+                    blob: info.next()?.to_string(),
+                    path: path.to_string(),
+                })
+            })
             .collect()
     }
 
@@ -98,3 +246,239 @@ impl GitClient {
             .expect("failed to execute git")
     }
 }
+
+//
+// Split the output of `git diff -U1 --full-index` into per-file sections
+//
+pub fn parse_diff(content: &str) -> Vec<FileDiff> {
+    let mut files = Vec::new();
+    let mut current: Option<FileDiff> = None;
+    let mut in_hunk = false;
+    // next line number in the old and new versions of the file
+    let mut old_number = 0;
+    let mut new_number = 0;
+    // last line seen in the old and new versions of the file
+    let mut old_previous: Option<String> = None;
+    let mut new_previous: Option<String> = None;
+
+    for line in str::lines(content) {
+        if line.starts_with("diff --git") {
+            if let Some(file) = current.take() {
+                files.push(file);
+            }
+
+            // NOTE: this could easily be broken by paths with spaces...
+            // we're going to assume that the repos do not contain any folder
+            // ending with " b/"
+
+            // 13 is the length of "diff --git a/"
+            let file_name: String = line.chars().skip(13).collect();
+            let file_name = file_name.split(" b/").next().unwrap().to_string();
+            current = Some(FileDiff::new(file_name));
+            in_hunk = false;
+            continue;
+        }
+
+        let file = match current.as_mut() {
+            Some(file) => file,
+            None => continue,
+        };
+
+        // @@ lines give where the hunk starts in the old and new versions, e.g. `@@ -3,2 +4 @@`
+        if line.starts_with("@@") {
+            let mut ranges = line.split_whitespace().skip(1);
+            old_number = ranges.next().map_or(0, hunk_start);
+            new_number = ranges.next().map_or(0, hunk_start);
+            old_previous = None;
+            new_previous = None;
+            in_hunk = true;
+            continue;
+        }
+
+        if !in_hunk {
+            if let Some(index) = line.strip_prefix("index ") {
+                let mut blobs = index.split_whitespace().next().unwrap_or("").split("..");
+                file.old_blob = blobs.next().and_then(blob_hash);
+                file.new_blob = blobs.next().and_then(blob_hash);
+            } else if line.starts_with("Binary files ") {
+                file.binary = true;
+            }
+
+            continue;
+        }
+
+        let text = line.get(1..).unwrap_or("");
+        let trimmed = text.trim().to_string();
+        let (change_type, number, previous) = match line.chars().next() {
+            Some('+') => {
+                new_number += 1;
+                let previous = new_previous.replace(trimmed.clone());
+                (ChangeType::Addition, new_number - 1, previous)
+            }
+            Some('-') => {
+                old_number += 1;
+                let previous = old_previous.replace(trimmed.clone());
+                (ChangeType::Removal, old_number - 1, previous)
+            }
+            Some(' ') => {
+                old_number += 1;
+                new_number += 1;
+                old_previous = Some(trimmed.clone());
+                new_previous = Some(trimmed);
+                continue;
+            }
+            _ => continue,
+        };
+
+        let indent = text.len() - text.trim_start().len();
+        file.lines.push(DiffLine {
+            change_type,
+            number,
+            column: text[..indent].chars().count() + 1,
+            text: trimmed,
+            previous,
+        });
+    }
+
+    if let Some(file) = current {
+        files.push(file);
+    }
+
+    files
+}
+
+// Line a hunk range like `-3,2` or `+4` starts at
+fn hunk_start(range: &str) -> usize {
+    range[1..]
+        .split(',')
+        .next()
+        .and_then(|start| start.parse().ok())
+        .unwrap_or(0)
+}
+
+fn blob_hash(hash: &str) -> Option<String> {
+    if hash.is_empty() || hash.chars().all(|c| c == '0') {
+        None
+    } else {
+        Some(hash.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIFF: &str = "\
+diff --git a/config.py b/config.py
+index 1111111111111111111111111111111111111111..2222222222222222222222222222222222222222 100644
+--- a/config.py
++++ b/config.py
+@@ -1,3 +1,3 @@
+ import os
+-KEY = 'old'
++KEY = 'new'
+ DEBUG = True
+@@ -10 +10,2 @@ def connect():
+ \tconn = None
++\tpassword = 'hunter2'
+diff --git a/new.txt b/new.txt
+new file mode 100644
+index 0000000000000000000000000000000000000000..3333333333333333333333333333333333333333
+--- /dev/null
++++ b/new.txt
+@@ -0,0 +1 @@
++token
+";
+
+    fn summary(line: &DiffLine) -> (ChangeType, usize, usize, &str, Option<&str>) {
+        (
+            line.change_type,
+            line.number,
+            line.column,
+            line.text.as_str(),
+            line.previous.as_deref(),
+        )
+    }
+
+    #[test]
+    fn hunk_start_with_and_without_counts() {
+        assert_eq!(hunk_start("-3,2"), 3);
+        assert_eq!(hunk_start("+4"), 4);
+        assert_eq!(hunk_start("-0,0"), 0);
+        assert_eq!(hunk_start("+x"), 0);
+    }
+
+    #[test]
+    fn parses_files_and_blobs() {
+        let files = parse_diff(DIFF);
+        assert_eq!(files.len(), 2);
+
+        assert_eq!(files[0].file_name, "config.py");
+        assert_eq!(
+            files[0].old_blob.as_deref(),
+            Some("1111111111111111111111111111111111111111")
+        );
+        assert_eq!(
+            files[0].new_blob.as_deref(),
+            Some("2222222222222222222222222222222222222222")
+        );
+
+        assert_eq!(files[1].file_name, "new.txt");
+        assert_eq!(files[1].old_blob, None);
+        assert_eq!(
+            files[1].new_blob.as_deref(),
+            Some("3333333333333333333333333333333333333333")
+        );
+    }
+
+    #[test]
+    fn numbers_lines_across_hunks() {
+        let files = parse_diff(DIFF);
+        let lines: Vec<_> = files[0].lines.iter().map(summary).collect();
+
+        assert_eq!(
+            lines,
+            vec![
+                // removals are numbered in the old file, additions in the new one
+                (ChangeType::Removal, 2, 1, "KEY = 'old'", Some("import os")),
+                (ChangeType::Addition, 2, 1, "KEY = 'new'", Some("import os")),
+                // a hunk without counts, and a tab-indented line starting at column 2
+                (
+                    ChangeType::Addition,
+                    11,
+                    2,
+                    "password = 'hunter2'",
+                    Some("conn = None")
+                ),
+            ]
+        );
+        assert_eq!(
+            summary(&files[1].lines[0]),
+            (ChangeType::Addition, 1, 1, "token", None)
+        );
+    }
+
+    #[test]
+    fn resets_previous_line_at_each_hunk() {
+        let diff = "\
+diff --git a/a.txt b/a.txt
+--- a/a.txt
++++ b/a.txt
+@@ -1,1 +1,2 @@
+ first
++second
+@@ -20,0 +21,1 @@
++third
+";
+        let files = parse_diff(diff);
+        let lines: Vec<_> = files[0].lines.iter().map(summary).collect();
+
+        assert_eq!(
+            lines,
+            vec![
+                (ChangeType::Addition, 2, 1, "second", Some("first")),
+                (ChangeType::Addition, 21, 1, "third", None),
+            ]
+        );
+    }
+}
@@ -0,0 +1,269 @@
+use csv;
+use definitions::Severity;
+use git::ChangeType;
+use output::{parse_timestamp, OutputFormat, OutputWriter, ScanMetadata};
+use serde_json::{self, Value};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use PatternMatch;
+
+//
+// Every match of the same secret by the same rule, collapsed into one result. Commits are
+// compared by date since matches come from many threads in no particular order.
+//
+#[derive(Serialize)]
+struct Finding {
+    description: String,
+    rule_id: String,
+    severity: Severity,
+    #[serde(serialize_with = "::serialize_list")]
+    tags: Vec<String>,
+    remediation: String,
+    text: String,
+    secret: String,
+    fingerprint: String,
+    occurrences: usize,
+    // the earliest commit that added the secret, or the earliest it was seen in if it was only
+    // ever seen being removed (e.g. when history is limited with --since)
+    first_commit: String,
+    first_commit_date: String,
+    // the latest commit that added or removed the secret
+    last_commit: String,
+    last_commit_date: String,
+    #[serde(serialize_with = "::serialize_list")]
+    repos: Vec<String>,
+    #[serde(serialize_with = "::serialize_list")]
+    paths: Vec<String>,
+    // whether the secret is still at HEAD in any of the repos it was found in, if branch tips
+    // were searched
+    present_at_head: Option<bool>,
+    // branches the secret is still at the tip of, in any of those repos
+    #[serde(serialize_with = "::serialize_list")]
+    branches: Vec<String>,
+    #[serde(skip_serializing)]
+    first: (bool, i64),
+    #[serde(skip_serializing)]
+    last: i64,
+}
+
+impl Finding {
+    fn new(pattern_match: &PatternMatch) -> Finding {
+        Finding {
+            description: pattern_match.description.clone(),
+            rule_id: pattern_match.rule_id.clone(),
+            severity: pattern_match.severity,
+            tags: pattern_match.tags.clone(),
+            remediation: pattern_match.remediation.clone(),
+            text: pattern_match.text.clone(),
+            secret: pattern_match.secret.clone(),
+            fingerprint: pattern_match.fingerprint.clone(),
+            occurrences: 0,
+            first_commit: String::new(),
+            first_commit_date: String::new(),
+            last_commit: String::new(),
+            last_commit_date: String::new(),
+            repos: Vec::new(),
+            paths: Vec::new(),
+            present_at_head: None,
+            branches: Vec::new(),
+            first: (true, i64::MAX),
+            last: i64::MIN,
+        }
+    }
+
+    fn add(&mut self, pattern_match: &PatternMatch) {
+        let time = parse_timestamp(&pattern_match.commit_date).unwrap_or(0);
+        // additions come before anything else, then the oldest commit
+        let first = (pattern_match.change_type != ChangeType::Addition, time);
+        if first < self.first {
+            self.first = first;
+            self.first_commit = pattern_match.commit_hash.clone();
+            self.first_commit_date = pattern_match.commit_date.clone();
+            self.text = pattern_match.text.clone();
+        }
+        if time > self.last {
+            self.last = time;
+            self.last_commit = pattern_match.commit_hash.clone();
+            self.last_commit_date = pattern_match.commit_date.clone();
+        }
+
+        self.occurrences += 1;
+        if let Some(present_at_head) = pattern_match.present_at_head {
+            self.present_at_head = Some(present_at_head || self.present_at_head == Some(true));
+        }
+        insert_sorted(&mut self.repos, &pattern_match.repo_path);
+        insert_sorted(&mut self.paths, &pattern_match.file);
+        for branch in &pattern_match.branches {
+            insert_sorted(&mut self.branches, branch);
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        let mut value = serde_json::to_value(self).expect("failed to serialize finding");
+        value["tags"] = Value::from(self.tags.clone());
+        value["repos"] = Value::from(self.repos.clone());
+        value["paths"] = Value::from(self.paths.clone());
+        value["branches"] = Value::from(self.branches.clone());
+        value
+    }
+}
+
+//
+// Groups matches by rule and secret, writing each group once the scan is done. Most severe
+// findings are written first.
+//
+pub struct GroupedOutput {
+    format: OutputFormat,
+    writer: Box<dyn Write>,
+    findings: BTreeMap<(Reverse<Severity>, String, String), Finding>,
+}
+
+impl GroupedOutput {
+    pub fn new(format: OutputFormat, writer: Box<dyn Write>) -> GroupedOutput {
+        GroupedOutput {
+            format,
+            writer,
+            findings: BTreeMap::new(),
+        }
+    }
+}
+
+impl OutputWriter for GroupedOutput {
+    fn write_match(&mut self, pattern_match: &PatternMatch) -> io::Result<()> {
+        self.findings
+            .entry((
+                Reverse(pattern_match.severity),
+                pattern_match.rule_id.clone(),
+                pattern_match.fingerprint.clone(),
+            ))
+            .or_insert_with(|| Finding::new(pattern_match))
+            .add(pattern_match);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn finish(&mut self, metadata: &ScanMetadata) -> io::Result<()> {
+        match self.format {
+            OutputFormat::Csv => {
+                let mut writer = csv::Writer::from_writer(&mut self.writer);
+                for finding in self.findings.values() {
+                    writer.serialize(finding).map_err(io::Error::from)?;
+                }
+                writer.flush()?;
+            }
+            OutputFormat::JsonLines => {
+                for finding in self.findings.values() {
+                    serde_json::to_writer(&mut self.writer, &finding.to_json())?;
+                    self.writer.write_all(b"\n")?;
+                }
+            }
+            // JSON, since SARIF and HTML reports group matches themselves
+            _ => {
+                let findings: Vec<Value> = self.findings.values().map(Finding::to_json).collect();
+                let mut scan = serde_json::to_value(metadata)?;
+                scan["findings"] = Value::from(findings.len());
+                serde_json::to_writer(
+                    &mut self.writer,
+                    &json!({ "findings": findings, "scan": scan }),
+                )?;
+                self.writer.write_all(b"\n")?;
+            }
+        }
+
+        self.writer.flush()
+    }
+}
+
+fn insert_sorted(list: &mut Vec<String>, value: &str) {
+    if let Err(index) = list.binary_search_by(|item| item.as_str().cmp(value)) {
+        list.insert(index, value.to_owned());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git::Commit;
+    use std::cell::RefCell;
+    use suppress::IgnoreFile;
+    use {check_patterns, load_definitions, output, ScanContext, ScanOptions};
+
+    // matches for each line, as the scan would report them
+    fn find(lines: &[&str]) -> Vec<PatternMatch> {
+        let (rules, _) = load_definitions(&["generic"], &[]);
+        let commit = Commit {
+            hash: String::new(),
+            parent: None,
+            date: String::new(),
+            author: String::new(),
+            subject: String::new(),
+            client: None,
+        };
+        let options = ScanOptions::default();
+        let ignore = IgnoreFile::default();
+        let found = RefCell::new(Vec::new());
+        let on_found = |matched: PatternMatch| found.borrow_mut().push(matched);
+        let ctx = ScanContext {
+            patterns: &rules.patterns,
+            files: &rules.files,
+            keys: &rules.keys,
+            allowlist: &rules.allowlist,
+            ignore: &ignore,
+            options: &options,
+            on_found: &on_found,
+            repo_path: "",
+            commit: &commit,
+        };
+        for line in lines {
+            check_patterns(
+                &ctx,
+                line,
+                None,
+                ChangeType::Addition,
+                "app.config",
+                "",
+                None,
+            );
+        }
+
+        let mut found = found.into_inner();
+        for pattern_match in &mut found {
+            pattern_match.fingerprint = output::fingerprint(pattern_match);
+        }
+        found
+    }
+
+    fn group(matches: &[PatternMatch]) -> GroupedOutput {
+        let mut output = GroupedOutput::new(OutputFormat::JsonLines, Box::new(io::sink()));
+        for pattern_match in matches {
+            output.write_match(pattern_match).unwrap();
+        }
+        output
+    }
+
+    #[test]
+    fn keeps_different_values_of_a_prefix_rule_apart() {
+        let found = find(&["Password=hunter2", "Password=correcthorse;User=admin"]);
+        let found: Vec<_> = found
+            .into_iter()
+            .filter(|m| m.rule_id == "password-property")
+            .collect();
+        assert_eq!(found.len(), 2);
+        assert_eq!(group(&found).findings.len(), 2);
+    }
+
+    #[test]
+    fn merges_the_same_value_of_a_prefix_rule() {
+        let found = find(&["Password=hunter2", "  <add value=\"Password=hunter2\" />"]);
+        let found: Vec<_> = found
+            .into_iter()
+            .filter(|m| m.rule_id == "password-property")
+            .collect();
+        assert_eq!(found.len(), 2);
+        assert_eq!(group(&found).findings.len(), 1);
+    }
+}
@@ -0,0 +1,325 @@
+use definitions::{MatchType, Severity};
+use output::{timestamp, OutputWriter, ScanMetadata};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as FmtWrite;
+use std::io::{self, Write};
+use PatternMatch;
+
+const SEVERITIES: &[Severity] = &[
+    Severity::Critical,
+    Severity::High,
+    Severity::Medium,
+    Severity::Low,
+    Severity::Info,
+];
+
+const STYLE: &str = r#"
+body { font-family: -apple-system, "Segoe UI", Helvetica, Arial, sans-serif; margin: 0; color: #1f2328; background: #f6f8fa; }
+header { background: #fff; border-bottom: 1px solid #d0d7de; padding: 16px 24px; position: sticky; top: 0; }
+h1 { font-size: 20px; margin: 0 0 4px; }
+h2 { font-size: 16px; margin: 24px 0 8px; }
+main { padding: 0 24px 24px; }
+.meta { color: #59636e; margin: 0 0 12px; }
+.counts span, .badge { display: inline-block; border-radius: 12px; padding: 2px 10px; margin-right: 6px; font-size: 12px; font-weight: 600; color: #fff; }
+.critical { background: #8b0000; } .high { background: #cf222e; } .medium { background: #bc4c00; } .low { background: #9a6700; } .info { background: #59636e; }
+.filters { margin-top: 12px; }
+.filters input[type=search] { width: 360px; padding: 4px 8px; margin-right: 12px; }
+details.rule { background: #fff; border: 1px solid #d0d7de; border-radius: 6px; margin: 8px 0; padding: 8px 12px; }
+details.secret { margin: 6px 0 6px 16px; }
+summary { cursor: pointer; }
+code { font-family: ui-monospace, Consolas, monospace; font-size: 12px; background: #eff2f5; padding: 1px 4px; border-radius: 4px; word-break: break-all; }
+.count { color: #59636e; font-size: 12px; margin-left: 6px; }
+.remediation { margin: 6px 0; color: #59636e; }
+table { border-collapse: collapse; margin: 6px 0; width: 100%; font-size: 12px; }
+th, td { border: 1px solid #d0d7de; padding: 4px 6px; text-align: left; vertical-align: top; }
+th { background: #f6f8fa; }
+pre { margin: 4px 0; white-space: pre-wrap; word-break: break-all; }
+.head { background: #0969da; margin-left: 6px; }
+.hidden { display: none; }
+"#;
+
+// Hides secrets that don't match the search box or checked severities, then any rules and
+// repos left without visible secrets
+const SCRIPT: &str = r#"
+function applyFilters() {
+  var search = document.getElementById("search").value.toLowerCase();
+  var severities = {};
+  document.querySelectorAll(".severity-filter").forEach(function (box) { severities[box.value] = box.checked; });
+  document.querySelectorAll("section.repo").forEach(function (repo) {
+    var repoVisible = false;
+    repo.querySelectorAll("details.rule").forEach(function (rule) {
+      var ruleVisible = false;
+      rule.querySelectorAll("details.secret").forEach(function (secret) {
+        var visible = severities[rule.dataset.severity] &&
+          (search === "" || secret.dataset.search.indexOf(search) !== -1 || rule.dataset.search.indexOf(search) !== -1);
+        secret.classList.toggle("hidden", !visible);
+        ruleVisible = ruleVisible || visible;
+      });
+      rule.classList.toggle("hidden", !ruleVisible);
+      repoVisible = repoVisible || ruleVisible;
+    });
+    repo.classList.toggle("hidden", !repoVisible);
+  });
+}
+document.getElementById("search").addEventListener("input", applyFilters);
+document.querySelectorAll(".severity-filter").forEach(function (box) { box.addEventListener("change", applyFilters); });
+"#;
+
+//
+// Writes a single self-contained HTML page for triaging matches, grouped by repo, rule and
+// secret. Matches are grouped once the scan is done, so they're kept until then.
+//
+pub struct HtmlOutput {
+    writer: Box<dyn Write>,
+    matches: Vec<PatternMatch>,
+}
+
+impl HtmlOutput {
+    pub fn new(writer: Box<dyn Write>) -> HtmlOutput {
+        HtmlOutput {
+            writer,
+            matches: Vec::new(),
+        }
+    }
+}
+
+// Matches of one rule in one repo, by fingerprint
+struct RuleGroup<'a> {
+    first: &'a PatternMatch,
+    secrets: BTreeMap<&'a str, Vec<&'a PatternMatch>>,
+}
+
+impl OutputWriter for HtmlOutput {
+    fn write_match(&mut self, pattern_match: &PatternMatch) -> io::Result<()> {
+        self.matches.push(pattern_match.clone());
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn finish(&mut self, metadata: &ScanMetadata) -> io::Result<()> {
+        let mut repos: BTreeMap<&str, BTreeMap<(Reverse<Severity>, &str), RuleGroup>> =
+            BTreeMap::new();
+        for pattern_match in &self.matches {
+            repos
+                .entry(&pattern_match.repo_path)
+                .or_default()
+                .entry((Reverse(pattern_match.severity), &pattern_match.rule_id))
+                .or_insert_with(|| RuleGroup {
+                    first: pattern_match,
+                    secrets: BTreeMap::new(),
+                })
+                .secrets
+                .entry(&pattern_match.fingerprint)
+                .or_default()
+                .push(pattern_match);
+        }
+
+        let mut html = String::new();
+        html.push_str("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n");
+        html.push_str("<title>sourcesecrets report</title>\n");
+        let _ = writeln!(html, "<style>{}</style>\n</head>\n<body>\n<header>", STYLE);
+        html.push_str("<h1>sourcesecrets report</h1>\n");
+        let _ = writeln!(
+            html,
+            "<p class=\"meta\">{} commit(s) in {} repo(s) scanned with {} rule(s) by {} {}, finished {}</p>",
+            metadata.commits,
+            metadata.repos.len(),
+            metadata.rules,
+            metadata.tool,
+            metadata.version,
+            timestamp(metadata.finished_at)
+        );
+
+        html.push_str("<div class=\"counts\">");
+        for severity in SEVERITIES {
+            let secrets = repos
+                .values()
+                .flat_map(|rules| rules.values())
+                .filter(|group| group.first.severity == *severity)
+                .map(|group| group.secrets.len())
+                .sum::<usize>();
+            let occurrences = self
+                .matches
+                .iter()
+                .filter(|m| m.severity == *severity)
+                .count();
+            let _ = write!(
+                html,
+                "<span class=\"{0}\" title=\"{2} occurrence(s)\">{0}: {1}</span>",
+                severity_name(*severity),
+                secrets,
+                occurrences
+            );
+        }
+        html.push_str("</div>\n<div class=\"filters\">");
+        html.push_str(
+            "<input type=\"search\" id=\"search\" placeholder=\"Filter by rule, secret, file, commit or author\">",
+        );
+        for severity in SEVERITIES {
+            let _ = write!(
+                html,
+                "<label><input type=\"checkbox\" class=\"severity-filter\" value=\"{0}\" checked> {0}</label> ",
+                severity_name(*severity)
+            );
+        }
+        html.push_str("</div>\n</header>\n<main>\n");
+
+        if repos.is_empty() {
+            html.push_str("<p>No secrets found.</p>\n");
+        }
+
+        for (repo, rules) in &repos {
+            let count: usize = rules
+                .values()
+                .flat_map(|group| group.secrets.values())
+                .map(Vec::len)
+                .sum();
+            let _ = writeln!(
+                html,
+                "<section class=\"repo\"><h2>{}<span class=\"count\">{} occurrence(s)</span></h2>",
+                escape(repo),
+                count
+            );
+
+            for group in rules.values() {
+                write_rule(&mut html, group);
+            }
+
+            html.push_str("</section>\n");
+        }
+
+        let _ = write!(
+            html,
+            "</main>\n<script>{}</script>\n</body>\n</html>\n",
+            SCRIPT
+        );
+
+        self.writer.write_all(html.as_bytes())?;
+        self.writer.flush()
+    }
+}
+
+fn write_rule(html: &mut String, group: &RuleGroup) {
+    let rule = group.first;
+    let severity = severity_name(rule.severity);
+    let _ = writeln!(
+        html,
+        "<details class=\"rule\" data-severity=\"{}\" data-search=\"{}\" open><summary><span class=\"badge {}\">{}</span>{} <code>{}</code><span class=\"count\">{} secret(s)</span></summary>",
+        severity,
+        escape(&format!("{} {} {}", rule.rule_id, rule.description, rule.tags.join(" ")).to_lowercase()),
+        severity,
+        severity,
+        escape(&rule.description),
+        escape(&rule.rule_id),
+        group.secrets.len()
+    );
+    if !rule.remediation.is_empty() {
+        let _ = writeln!(
+            html,
+            "<p class=\"remediation\">{}</p>",
+            escape(&rule.remediation)
+        );
+    }
+
+    for occurrences in group.secrets.values() {
+        let first = occurrences[0];
+        // a file rule's secret is the whole file or a description of it, so name the file
+        let label = if first.match_type == MatchType::File {
+            &first.file
+        } else {
+            &first.secret
+        };
+
+        let mut search = String::new();
+        for m in occurrences {
+            search.push_str(&format!(
+                "{} {} {} {} {} ",
+                m.secret, m.text, m.file, m.commit_hash, m.commit_author
+            ));
+        }
+
+        // secrets that are still there need fixing first, the rest are only in history
+        let mut status = String::new();
+        if occurrences.iter().any(|m| m.present_at_head == Some(true)) {
+            status.push_str("<span class=\"badge head\">at HEAD</span>");
+        }
+        let branches: BTreeSet<&str> = occurrences
+            .iter()
+            .flat_map(|m| m.branches.iter().map(String::as_str))
+            .collect();
+        if !branches.is_empty() {
+            let branches: Vec<&str> = branches.into_iter().collect();
+            let _ = write!(
+                status,
+                "<span class=\"badge head\" title=\"{}\">on {} branch(es)</span>",
+                escape(&branches.join(", ")),
+                branches.len()
+            );
+        }
+
+        let _ = writeln!(
+            html,
+            "<details class=\"secret\" data-search=\"{}\"><summary><code>{}</code>{}<span class=\"count\">{} occurrence(s)</span></summary>",
+            escape(&search.to_lowercase()),
+            escape(label),
+            status,
+            occurrences.len()
+        );
+        html.push_str("<table>\n<tr><th>Commit</th><th>Date</th><th>Author</th><th>File</th><th>Change</th><th>Message</th><th>Context</th></tr>\n");
+        for m in occurrences {
+            let mut file = m.file.clone();
+            if let Some(line_number) = m.line_number {
+                file = format!("{}:{}", file, line_number);
+            }
+            if !m.location.is_empty() {
+                file = format!("{} ({})", file, m.location);
+            }
+
+            let _ = writeln!(
+                html,
+                "<tr><td><code title=\"{0}\">{1}</code></td><td>{2}</td><td>{3}</td><td><code>{4}</code></td><td>{5:?}</td><td>{6}</td><td><details><summary>Show</summary><pre>{7}</pre></details></td></tr>",
+                escape(&m.commit_hash),
+                escape(&m.commit_hash.chars().take(10).collect::<String>()),
+                escape(&m.commit_date),
+                escape(&m.commit_author),
+                escape(&file),
+                m.change_type,
+                escape(&m.commit_message),
+                escape(&m.text)
+            );
+        }
+        html.push_str("</table>\n</details>\n");
+    }
+
+    html.push_str("</details>\n");
+}
+
+fn severity_name(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Critical => "critical",
+        Severity::High => "high",
+        Severity::Medium => "medium",
+        Severity::Low => "low",
+        Severity::Info => "info",
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
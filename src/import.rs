@@ -0,0 +1,760 @@
+use definitions::{Config, FilePattern, Filter, FilterField, FilterTarget, Pattern};
+use regex::{self, Regex};
+use serde_json;
+use serde_yaml;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::str::FromStr;
+use toml;
+
+/// Other scanners' rule formats that can be converted to definitions
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Format {
+    /// gitleaks TOML configuration, with `[[rules]]` and allowlists
+    Gitleaks,
+    /// trufflehog's regexes.json or its custom detectors YAML
+    Trufflehog,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "gitleaks" => Ok(Format::Gitleaks),
+            "trufflehog" => Ok(Format::Trufflehog),
+            _ => Err(format!(
+                "unknown format {} (expected gitleaks or trufflehog)",
+                s
+            )),
+        }
+    }
+}
+
+//
+// Work out which format a rule file is in from its extension and contents. Returns `None` for
+// sourcesecrets' own definitions.
+//
+pub fn detect(path: &Path, contents: &str) -> Option<Format> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") | Some("yaml") | Some("yml") => Some(Format::Trufflehog),
+        _ => match contents.parse::<toml::Value>() {
+            Ok(toml::Value::Table(ref table)) if table.contains_key("rules") => {
+                Some(Format::Gitleaks)
+            }
+            _ => None,
+        },
+    }
+}
+
+//
+// Convert a rule file to definitions. Anything that can't be carried over is listed in the
+// config's warnings rather than failing the import.
+//
+pub fn import(format: Format, contents: &str) -> Result<Config, String> {
+    match format {
+        Format::Gitleaks => import_gitleaks(contents),
+        Format::Trufflehog => import_trufflehog(contents),
+    }
+}
+
+#[derive(Deserialize)]
+struct GitleaksConfig {
+    title: Option<String>,
+    extend: Option<toml::Value>,
+    #[serde(default)]
+    rules: Vec<GitleaksRule>,
+    allowlist: Option<GitleaksAllowlist>,
+    #[serde(default)]
+    allowlists: Vec<GitleaksAllowlist>,
+
+    #[serde(flatten)]
+    other: BTreeMap<String, toml::Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GitleaksRule {
+    id: String,
+    description: Option<String>,
+    regex: Option<String>,
+    secret_group: Option<usize>,
+    entropy: Option<f64>,
+    path: Option<String>,
+    keywords: Option<Vec<String>>,
+    tags: Option<Vec<String>>,
+    allowlist: Option<GitleaksAllowlist>,
+    #[serde(default)]
+    allowlists: Vec<GitleaksAllowlist>,
+
+    #[serde(flatten)]
+    other: BTreeMap<String, toml::Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GitleaksAllowlist {
+    description: Option<String>,
+    condition: Option<String>,
+    regex_target: Option<String>,
+    #[serde(default)]
+    regexes: Vec<String>,
+    #[serde(default)]
+    paths: Vec<String>,
+    #[serde(default)]
+    commits: Vec<String>,
+    #[serde(default)]
+    stopwords: Vec<String>,
+    target_rules: Option<Vec<String>>,
+
+    #[serde(flatten)]
+    other: BTreeMap<String, toml::Value>,
+}
+
+fn import_gitleaks(contents: &str) -> Result<Config, String> {
+    let gitleaks: GitleaksConfig = toml::from_str(contents).map_err(|e| e.to_string())?;
+    let mut config = Config::default();
+    let mut patterns = Vec::new();
+    let mut files = Vec::new();
+    let mut filters = Vec::new();
+
+    if gitleaks.extend.is_some() {
+        config
+            .warnings
+            .push("[extend] isn't supported, only the rules in this file are imported".to_owned());
+    }
+    unsupported_fields(&mut config.warnings, "the config", gitleaks.other.keys());
+
+    let mut ids = Vec::new();
+    for rule in gitleaks.rules {
+        let name = format!("rule {}", rule.id);
+        unsupported_fields(&mut config.warnings, &name, rule.other.keys());
+
+        let description = rule.description.clone().unwrap_or_else(|| rule.id.clone());
+        match (rule.regex, rule.path) {
+            (Some(regex), path) => {
+                if path.is_some() {
+                    config.warnings.push(format!(
+                        "{}: path is ignored, the pattern is checked in every file",
+                        name
+                    ));
+                }
+
+                patterns.push(Pattern {
+                    id: Some(rule.id.clone()),
+                    description,
+                    tags: rule.tags,
+                    secret_group: rule.secret_group.or_else(|| first_group(&regex)),
+                    min_entropy: rule.entropy,
+                    keywords: rule.keywords,
+                    // Go's regexes are case sensitive unless they say otherwise
+                    case_sensitive: Some(true),
+                    pattern: regex,
+                    ..Default::default()
+                });
+            }
+            (None, Some(path)) => {
+                // a rule with only a path flags the file itself
+                files.push(FilePattern {
+                    id: Some(rule.id.clone()),
+                    description,
+                    tags: rule.tags,
+                    regex: Some(path),
+                    ..Default::default()
+                });
+            }
+            (None, None) => {
+                config
+                    .warnings
+                    .push(format!("{}: has neither a regex nor a path, skipped", name));
+                continue;
+            }
+        }
+
+        let id = rule.id;
+        let allowlists = rule.allowlist.into_iter().chain(rule.allowlists);
+        for (i, allowlist) in allowlists.enumerate() {
+            let description = allowlist
+                .description
+                .clone()
+                .unwrap_or_else(|| format!("Allowlist {} for {}", i + 1, id));
+            gitleaks_allowlist_filters(
+                &mut filters,
+                &mut config.warnings,
+                &description,
+                allowlist,
+                vec![id.clone()],
+            );
+        }
+
+        ids.push(id);
+    }
+
+    // global allowlists apply to every imported rule, and not to rules defined elsewhere
+    let allowlists = gitleaks.allowlist.into_iter().chain(gitleaks.allowlists);
+    for (i, allowlist) in allowlists.enumerate() {
+        let title = gitleaks.title.as_ref().map_or("gitleaks", |title| title);
+        let description = allowlist
+            .description
+            .clone()
+            .unwrap_or_else(|| format!("{} allowlist {}", title, i + 1));
+        let targets = allowlist
+            .target_rules
+            .clone()
+            .unwrap_or_else(|| ids.clone());
+        gitleaks_allowlist_filters(
+            &mut filters,
+            &mut config.warnings,
+            &description,
+            allowlist,
+            targets,
+        );
+    }
+
+    config.patterns = some_if_any(patterns);
+    config.files = some_if_any(files);
+    config.filters = some_if_any(filters);
+    Ok(config)
+}
+
+//
+// Convert a gitleaks allowlist to filters for the given rules. Each of its lists becomes its own
+// filter, so allowlists that need all of them to match are skipped.
+//
+fn gitleaks_allowlist_filters(
+    filters: &mut Vec<Filter>,
+    warnings: &mut Vec<String>,
+    description: &str,
+    allowlist: GitleaksAllowlist,
+    rules: Vec<String>,
+) {
+    let name = format!("allowlist \"{}\"", description);
+    unsupported_fields(warnings, &name, allowlist.other.keys());
+
+    let lists = [
+        &allowlist.regexes,
+        &allowlist.paths,
+        &allowlist.commits,
+        &allowlist.stopwords,
+    ];
+    let is_and = allowlist
+        .condition
+        .as_ref()
+        .is_some_and(|condition| condition.eq_ignore_ascii_case("and"));
+    if is_and && lists.iter().filter(|list| !list.is_empty()).count() > 1 {
+        warnings.push(format!(
+            "{}: condition = \"AND\" isn't supported, skipped so no matches are dropped",
+            name
+        ));
+        return;
+    }
+
+    let target = match allowlist.regex_target.as_deref() {
+        None | Some("secret") => FilterTarget::Secret,
+        Some("line") => FilterTarget::Line,
+        Some(target) => {
+            warnings.push(format!(
+                "{}: regexTarget = \"{}\" is checked against the secret instead",
+                name, target
+            ));
+            FilterTarget::Secret
+        }
+    };
+
+    let filter = |suffix: &str, pattern: String| Filter {
+        description: format!("{} ({})", description, suffix),
+        pattern,
+        rules: Some(rules.clone()),
+        ..Default::default()
+    };
+
+    if !allowlist.regexes.is_empty() {
+        filters.push(Filter {
+            target: Some(target),
+            case_sensitive: Some(true),
+            ..filter("regexes", alternation(&allowlist.regexes))
+        });
+    }
+
+    if !allowlist.paths.is_empty() {
+        filters.push(Filter {
+            fields: Some(vec![FilterField::File]),
+            case_sensitive: Some(true),
+            ..filter("paths", alternation(&allowlist.paths))
+        });
+    }
+
+    if !allowlist.commits.is_empty() {
+        let commits: Vec<String> = allowlist
+            .commits
+            .iter()
+            .map(|commit| regex::escape(commit))
+            .collect();
+        filters.push(Filter {
+            fields: Some(vec![FilterField::Commit]),
+            ..filter("commits", format!("^(?:{})", alternation(&commits)))
+        });
+    }
+
+    if !allowlist.stopwords.is_empty() {
+        let stopwords: Vec<String> = allowlist
+            .stopwords
+            .iter()
+            .map(|stopword| regex::escape(stopword))
+            .collect();
+        filters.push(Filter {
+            target: Some(FilterTarget::Secret),
+            ..filter("stopwords", alternation(&stopwords))
+        });
+    }
+}
+
+#[derive(Deserialize)]
+struct TrufflehogConfig {
+    #[serde(default)]
+    detectors: Vec<TrufflehogDetector>,
+
+    #[serde(flatten)]
+    other: BTreeMap<String, serde_yaml::Value>,
+}
+
+#[derive(Deserialize)]
+struct TrufflehogDetector {
+    name: String,
+    keywords: Option<Vec<String>>,
+    #[serde(default)]
+    regex: BTreeMap<String, String>,
+    entropy: Option<f64>,
+    #[serde(default)]
+    exclude_words: Vec<String>,
+    #[serde(default)]
+    exclude_regexes_capture: Vec<String>,
+    #[serde(default)]
+    exclude_regexes_match: Vec<String>,
+    verify: Option<serde_yaml::Value>,
+
+    #[serde(flatten)]
+    other: BTreeMap<String, serde_yaml::Value>,
+}
+
+//
+// trufflehog's rules are either the flat `name -> regex` map from regexes.json, or custom
+// detectors defined in YAML
+//
+fn import_trufflehog(contents: &str) -> Result<Config, String> {
+    if let Ok(regexes) = serde_json::from_str::<BTreeMap<String, String>>(contents) {
+        let patterns = regexes
+            .into_iter()
+            .map(|(name, regex)| Pattern {
+                id: Some(slug(&name)),
+                description: name,
+                secret_group: first_group(&regex),
+                case_sensitive: Some(true),
+                pattern: regex,
+                ..Default::default()
+            })
+            .collect();
+
+        return Ok(Config {
+            patterns: some_if_any(patterns),
+            ..Default::default()
+        });
+    }
+
+    // YAML is a superset of JSON, so this also reports errors for malformed regexes.json files
+    let trufflehog: TrufflehogConfig = serde_yaml::from_str(contents).map_err(|e| e.to_string())?;
+    let mut config = Config::default();
+    let mut patterns = Vec::new();
+    let mut filters = Vec::new();
+
+    unsupported_fields(&mut config.warnings, "the config", trufflehog.other.keys());
+
+    for detector in trufflehog.detectors {
+        let name = format!("detector {}", detector.name);
+        unsupported_fields(&mut config.warnings, &name, detector.other.keys());
+
+        if detector.verify.is_some() {
+            config.warnings.push(format!(
+                "{}: verify isn't supported, matches are reported without verification",
+                name
+            ));
+        }
+        if detector.regex.len() > 1 {
+            config.warnings.push(format!(
+                "{}: its regexes are imported as separate patterns, each reported on its own \
+                 rather than only when all of them match",
+                name
+            ));
+        }
+
+        let mut ids = Vec::new();
+        for (regex_name, regex) in detector.regex.iter() {
+            let id = if detector.regex.len() > 1 {
+                format!("{}-{}", slug(&detector.name), slug(regex_name))
+            } else {
+                slug(&detector.name)
+            };
+
+            patterns.push(Pattern {
+                id: Some(id.clone()),
+                description: if detector.regex.len() > 1 {
+                    format!("{} ({})", detector.name, regex_name)
+                } else {
+                    detector.name.clone()
+                },
+                secret_group: first_group(regex),
+                min_entropy: detector.entropy,
+                keywords: detector.keywords.clone(),
+                case_sensitive: Some(true),
+                pattern: regex.clone(),
+                ..Default::default()
+            });
+            ids.push(id);
+        }
+
+        if ids.is_empty() {
+            config
+                .warnings
+                .push(format!("{}: has no regexes, skipped", name));
+            continue;
+        }
+
+        let filter = |suffix: &str, pattern: String, target: FilterTarget| Filter {
+            description: format!("{} ({})", detector.name, suffix),
+            pattern,
+            rules: Some(ids.clone()),
+            target: Some(target),
+            ..Default::default()
+        };
+
+        if !detector.exclude_words.is_empty() {
+            let words: Vec<String> = detector
+                .exclude_words
+                .iter()
+                .map(|word| regex::escape(word))
+                .collect();
+            filters.push(filter(
+                "exclude_words",
+                alternation(&words),
+                FilterTarget::Secret,
+            ));
+        }
+
+        if !detector.exclude_regexes_capture.is_empty() {
+            filters.push(Filter {
+                case_sensitive: Some(true),
+                ..filter(
+                    "exclude_regexes_capture",
+                    alternation(&detector.exclude_regexes_capture),
+                    FilterTarget::Secret,
+                )
+            });
+        }
+
+        if !detector.exclude_regexes_match.is_empty() {
+            config.warnings.push(format!(
+                "{}: exclude_regexes_match is checked against the whole line",
+                name
+            ));
+            filters.push(Filter {
+                case_sensitive: Some(true),
+                ..filter(
+                    "exclude_regexes_match",
+                    alternation(&detector.exclude_regexes_match),
+                    FilterTarget::Line,
+                )
+            });
+        }
+    }
+
+    config.patterns = some_if_any(patterns);
+    config.filters = some_if_any(filters);
+    Ok(config)
+}
+
+fn unsupported_fields<'a, I>(warnings: &mut Vec<String>, name: &str, fields: I)
+where
+    I: Iterator<Item = &'a String>,
+{
+    for field in fields {
+        warnings.push(format!("{}: {} isn't supported, ignored", name, field));
+    }
+}
+
+// Both scanners report the first capture group as the secret when the rule doesn't say otherwise
+fn first_group(pattern: &str) -> Option<usize> {
+    match Regex::new(pattern) {
+        Ok(regex) if regex.captures_len() > 1 => Some(1),
+        _ => None,
+    }
+}
+
+fn alternation(patterns: &[String]) -> String {
+    if patterns.len() == 1 {
+        return patterns[0].clone();
+    }
+
+    patterns
+        .iter()
+        .map(|pattern| format!("(?:{})", pattern))
+        .collect::<Vec<_>>()
+        .join("|")
+}
+
+// Turn a name like "Slack Token" into an id like "slack-token"
+fn slug(name: &str) -> String {
+    name.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+fn some_if_any<T>(rules: Vec<T>) -> Option<Vec<T>> {
+    if rules.is_empty() {
+        None
+    } else {
+        Some(rules)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GITLEAKS: &str = r#"
+title = "Example"
+minVersion = "v8.0.0"
+
+[extend]
+useDefault = true
+
+[[rules]]
+id = "github-pat"
+description = "GitHub personal access token"
+regex = '''ghp_[0-9a-zA-Z]{36}'''
+keywords = ["ghp_"]
+tags = ["github"]
+
+[[rules]]
+id = "generic-password"
+regex = '''password\s*=\s*"([^"]+)"'''
+entropy = 3.0
+path = '''\.py$'''
+report = false
+[rules.allowlist]
+regexes = ['''example''']
+regexTarget = "line"
+
+[[rules]]
+id = "pkcs12-file"
+path = '''\.p12$'''
+
+[[rules]]
+id = "nothing"
+
+[allowlist]
+description = "Global allowlist"
+paths = ['''^vendor/''', '''\.lock$''']
+commits = ["abc123"]
+stopwords = ["dummy"]
+
+[[allowlists]]
+condition = "AND"
+regexes = ["test"]
+paths = ["tests/"]
+"#;
+
+    const TRUFFLEHOG: &str = r#"
+detectors:
+  - name: Internal API
+    keywords: [internal]
+    regex:
+      key: 'internal_([a-z0-9]{32})'
+    entropy: 3.5
+    exclude_words: [example]
+    exclude_regexes_match: ['test_.*']
+    verify:
+      - endpoint: https://example.com/verify
+  - name: Pair
+    regex:
+      id: 'pair_id_[0-9]+'
+      secret: 'pair_secret_[a-z]+'
+    primary_regex_name: id
+  - name: Empty
+"#;
+
+    fn ids<T, F: Fn(&T) -> Option<String>>(rules: &Option<Vec<T>>, id: F) -> Vec<String> {
+        rules.iter().flatten().filter_map(id).collect()
+    }
+
+    #[test]
+    fn detects_formats() {
+        assert_eq!(
+            detect(Path::new("gitleaks.toml"), GITLEAKS),
+            Some(Format::Gitleaks)
+        );
+        assert_eq!(
+            detect(Path::new("detectors.yaml"), TRUFFLEHOG),
+            Some(Format::Trufflehog)
+        );
+        assert_eq!(
+            detect(
+                Path::new("definitions.toml"),
+                "[[patterns]]\npattern = \"x\""
+            ),
+            None
+        );
+        assert!("Gitleaks".parse::<Format>().is_ok());
+        assert!("detect-secrets".parse::<Format>().is_err());
+    }
+
+    #[test]
+    fn imports_gitleaks_rules() {
+        let config = import(Format::Gitleaks, GITLEAKS).unwrap();
+
+        assert_eq!(
+            ids(&config.patterns, |p: &Pattern| p.id.clone()),
+            vec!["github-pat", "generic-password"]
+        );
+        let patterns = config.patterns.as_ref().unwrap();
+        assert_eq!(patterns[0].pattern, "ghp_[0-9a-zA-Z]{36}");
+        assert_eq!(patterns[0].description, "GitHub personal access token");
+        assert_eq!(patterns[0].secret_group, None);
+        assert_eq!(patterns[0].case_sensitive, Some(true));
+        assert_eq!(patterns[0].keywords, Some(vec!["ghp_".to_owned()]));
+        // the first group is the secret, and rules without a description are named by their id
+        assert_eq!(patterns[1].description, "generic-password");
+        assert_eq!(patterns[1].secret_group, Some(1));
+        assert_eq!(patterns[1].min_entropy, Some(3.0));
+
+        assert_eq!(
+            ids(&config.files, |f: &FilePattern| f.id.clone()),
+            vec!["pkcs12-file"]
+        );
+        assert_eq!(
+            config.files.as_ref().unwrap()[0].regex.as_deref(),
+            Some(r"\.p12$")
+        );
+    }
+
+    #[test]
+    fn imports_gitleaks_allowlists_as_filters() {
+        let config = import(Format::Gitleaks, GITLEAKS).unwrap();
+        let filters = config.filters.unwrap();
+        let summary: Vec<_> = filters
+            .iter()
+            .map(|f| (f.description.as_str(), f.pattern.as_str()))
+            .collect();
+
+        assert_eq!(
+            summary,
+            vec![
+                ("Allowlist 1 for generic-password (regexes)", "example"),
+                ("Global allowlist (paths)", r"(?:^vendor/)|(?:\.lock$)"),
+                ("Global allowlist (commits)", "^(?:abc123)"),
+                ("Global allowlist (stopwords)", "dummy"),
+            ]
+        );
+        assert_eq!(filters[0].target, Some(FilterTarget::Line));
+        assert_eq!(filters[0].rules, Some(vec!["generic-password".to_owned()]));
+        assert_eq!(filters[1].fields, Some(vec![FilterField::File]));
+        assert_eq!(filters[2].fields, Some(vec![FilterField::Commit]));
+        assert_eq!(filters[3].target, Some(FilterTarget::Secret));
+        // global allowlists only apply to the rules that were imported with them
+        assert_eq!(
+            filters[1].rules,
+            Some(vec![
+                "github-pat".to_owned(),
+                "generic-password".to_owned(),
+                "pkcs12-file".to_owned(),
+            ])
+        );
+    }
+
+    #[test]
+    fn warns_about_unsupported_gitleaks_fields() {
+        let config = import(Format::Gitleaks, GITLEAKS).unwrap();
+
+        assert_eq!(
+            config.warnings,
+            vec![
+                "[extend] isn't supported, only the rules in this file are imported",
+                "the config: minVersion isn't supported, ignored",
+                "rule generic-password: report isn't supported, ignored",
+                "rule generic-password: path is ignored, the pattern is checked in every file",
+                "rule nothing: has neither a regex nor a path, skipped",
+                "allowlist \"Example allowlist 2\": condition = \"AND\" isn't supported, skipped \
+                 so no matches are dropped",
+            ]
+        );
+    }
+
+    #[test]
+    fn imports_trufflehog_detectors() {
+        let config = import(Format::Trufflehog, TRUFFLEHOG).unwrap();
+
+        assert_eq!(
+            ids(&config.patterns, |p: &Pattern| p.id.clone()),
+            vec!["internal-api", "pair-id", "pair-secret"]
+        );
+        let patterns = config.patterns.as_ref().unwrap();
+        assert_eq!(patterns[0].pattern, "internal_([a-z0-9]{32})");
+        assert_eq!(patterns[0].secret_group, Some(1));
+        assert_eq!(patterns[0].min_entropy, Some(3.5));
+        assert_eq!(patterns[0].keywords, Some(vec!["internal".to_owned()]));
+        assert_eq!(patterns[1].description, "Pair (id)");
+
+        let filters = config.filters.as_ref().unwrap();
+        let summary: Vec<_> = filters
+            .iter()
+            .map(|f| (f.description.as_str(), f.pattern.as_str(), f.target))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    "Internal API (exclude_words)",
+                    "example",
+                    Some(FilterTarget::Secret)
+                ),
+                (
+                    "Internal API (exclude_regexes_match)",
+                    "test_.*",
+                    Some(FilterTarget::Line)
+                ),
+            ]
+        );
+        assert_eq!(filters[0].rules, Some(vec!["internal-api".to_owned()]));
+
+        assert_eq!(
+            config.warnings,
+            vec![
+                "detector Internal API: verify isn't supported, matches are reported without \
+                 verification",
+                "detector Internal API: exclude_regexes_match is checked against the whole line",
+                "detector Pair: primary_regex_name isn't supported, ignored",
+                "detector Pair: its regexes are imported as separate patterns, each reported on \
+                 its own rather than only when all of them match",
+                "detector Empty: has no regexes, skipped",
+            ]
+        );
+    }
+
+    #[test]
+    fn imports_trufflehog_regexes_json() {
+        let config = import(
+            Format::Trufflehog,
+            r#"{"Slack Token": "(xox[pboa]-[0-9]{12}-[0-9]{12}-[0-9a-zA-Z]{24})"}"#,
+        )
+        .unwrap();
+        let patterns = config.patterns.unwrap();
+
+        assert_eq!(patterns.len(), 1);
+        assert_eq!(patterns[0].id.as_deref(), Some("slack-token"));
+        assert_eq!(patterns[0].description, "Slack Token");
+        assert_eq!(patterns[0].secret_group, Some(1));
+        assert!(config.warnings.is_empty());
+        assert!(import(Format::Trufflehog, "detectors: [").is_err());
+    }
+}
@@ -0,0 +1,394 @@
+use definitions::{
+    compile_allowlist, compile_file_patterns, compile_filters, compile_key_patterns,
+    compile_patterns, Config, MatchType, Rule, RuleError,
+};
+use regex::{Regex, RegexBuilder};
+use regex_syntax::hir::{Hir, HirKind};
+use std::collections::{HashMap, HashSet};
+use toml;
+
+/// Largest compiled regex we'll accept without complaint, rules over this are slow to scan with
+const MAX_REGEX_SIZE: usize = 1 << 20;
+
+/// Ordinary lines of code and prose that no rule should ever match
+const PROBE_LINES: &[&str] = &[
+    "using System;",
+    "import os",
+    "return 0;",
+    "}",
+    "<div class=\"container\">",
+    "# Introduction",
+    "for (int i = 0; i < 10; i++) {",
+    "Hello, world!",
+    "name = \"example\"",
+    "2018-01-01 12:00:00",
+];
+
+/// Ordinary paths that no file rule should match by path alone
+const PROBE_PATHS: &[&str] = &["README.md", "src/main.rs", "docs/index.html"];
+
+/// A problem found in a definitions file
+pub struct Problem {
+    /// 1-based line the problem is on, if we know it
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+//
+// Check a definitions file for rules that won't compile, duplicate ids and descriptions,
+// filters and overrides that can never apply and regexes that are too broad or too expensive.
+// `other_ids` are the ids of rules defined elsewhere, e.g. in built-in packs or included files.
+//
+pub fn check_definitions(contents: &str, other_ids: &HashSet<String>) -> Vec<Problem> {
+    let mut config: Config = match toml::from_str(contents) {
+        Ok(config) => config,
+        Err(e) => {
+            return vec![Problem {
+                line: e.line_col().map(|(line, _)| line + 1),
+                message: format!("could not parse definitions: {}", e),
+            }];
+        }
+    };
+
+    let mut problems = Vec::new();
+    let mut patterns = config.patterns.take().unwrap_or_default();
+    let mut files = config.files.take().unwrap_or_default();
+    let mut keys = config.keys.take().unwrap_or_default();
+    let mut filters = config.filters.take().unwrap_or_default();
+
+    let sections = [
+        ("patterns", section_lines(contents, "patterns")),
+        ("files", section_lines(contents, "files")),
+        ("keys", section_lines(contents, "keys")),
+        ("filters", section_lines(contents, "filters")),
+    ];
+    let line_of = |section: &str, index: usize| -> Option<usize> {
+        sections
+            .iter()
+            .find(|(name, _)| *name == section)
+            .and_then(|(_, lines)| lines.get(index).cloned())
+    };
+
+    let mut report = |section: &str, errors: Vec<RuleError>, descriptions: &[&str]| {
+        for error in errors {
+            problems.push(Problem {
+                line: line_of(section, error.index),
+                message: format!(
+                    "{} \"{}\": {}",
+                    section, descriptions[error.index], error.message
+                ),
+            });
+        }
+    };
+
+    let errors = compile_patterns(&mut patterns);
+    report(
+        "patterns",
+        errors,
+        &patterns
+            .iter()
+            .map(|p| p.description.as_str())
+            .collect::<Vec<_>>(),
+    );
+    let errors = compile_file_patterns(&mut files);
+    report(
+        "files",
+        errors,
+        &files
+            .iter()
+            .map(|f| f.description.as_str())
+            .collect::<Vec<_>>(),
+    );
+    let errors = compile_key_patterns(&mut keys);
+    report(
+        "keys",
+        errors,
+        &keys
+            .iter()
+            .map(|k| k.description.as_str())
+            .collect::<Vec<_>>(),
+    );
+    let errors = compile_filters(&mut filters);
+    report(
+        "filters",
+        errors,
+        &filters
+            .iter()
+            .map(|f| f.description.as_str())
+            .collect::<Vec<_>>(),
+    );
+
+    if let Some(mut allowlist) = config.allowlist.take() {
+        if let Err(e) = compile_allowlist(&mut allowlist) {
+            problems.push(Problem {
+                line: contents
+                    .lines()
+                    .position(|l| l.trim_start().starts_with("[allowlist]"))
+                    .map(|i| i + 1),
+                message: format!("allowlist: {}", e),
+            });
+        }
+    }
+
+    // every rule along with its section and line so problems can point at it
+    let mut rules: Vec<(&str, Option<usize>, &dyn Rule)> = Vec::new();
+    rules.extend(
+        patterns
+            .iter()
+            .enumerate()
+            .map(|(i, p)| ("patterns", line_of("patterns", i), p as &dyn Rule)),
+    );
+    rules.extend(
+        files
+            .iter()
+            .enumerate()
+            .map(|(i, f)| ("files", line_of("files", i), f as &dyn Rule)),
+    );
+    rules.extend(
+        keys.iter()
+            .enumerate()
+            .map(|(i, k)| ("keys", line_of("keys", i), k as &dyn Rule)),
+    );
+
+    let mut ids = HashMap::new();
+    let mut descriptions = HashMap::new();
+    for (section, line, rule) in &rules {
+        if let Some(id) = rule.id() {
+            match ids.get(id) {
+                Some(first) => problems.push(Problem {
+                    line: *line,
+                    message: format!("{} id {} is already used{}", section, id, on_line(*first)),
+                }),
+                None => {
+                    ids.insert(id, *line);
+                }
+            }
+        }
+
+        let key = (*section, rule.description());
+        match descriptions.get(&key) {
+            Some(first) => problems.push(Problem {
+                line: *line,
+                message: format!(
+                    "{} description \"{}\" is already used{}",
+                    section,
+                    rule.description(),
+                    on_line(*first)
+                ),
+            }),
+            None => {
+                descriptions.insert(key, *line);
+            }
+        }
+    }
+
+    let mut filter_descriptions = HashMap::new();
+    for (i, filter) in filters.iter().enumerate() {
+        let line = line_of("filters", i);
+        match filter_descriptions.get(filter.description.as_str()) {
+            Some(first) => problems.push(Problem {
+                line,
+                message: format!(
+                    "filters description \"{}\" is already used{}",
+                    filter.description,
+                    on_line(*first)
+                ),
+            }),
+            None => {
+                filter_descriptions.insert(filter.description.as_str(), line);
+            }
+        }
+
+        if !filter.enabled.unwrap_or(true) {
+            continue;
+        }
+
+        // filters target rules by id, or by description for rules without one
+        if let Some(targets) = filter.rules.as_ref() {
+            for target in targets {
+                if !rules.iter().any(|(_, _, rule)| rule.rule_id() == target)
+                    && !other_ids.contains(target)
+                {
+                    problems.push(Problem {
+                        line,
+                        message: format!(
+                            "filters \"{}\" can never apply: no rule has the id {}",
+                            filter.description, target
+                        ),
+                    });
+                }
+            }
+        } else {
+            let match_types = filter
+                .match_types
+                .clone()
+                .unwrap_or_else(|| vec![MatchType::Pattern]);
+            let has_rules = |match_type: &MatchType| match match_type {
+                MatchType::Pattern => !patterns.is_empty(),
+                MatchType::File => !files.is_empty(),
+                MatchType::Key => !keys.is_empty(),
+            };
+            if !match_types.iter().any(has_rules) {
+                problems.push(Problem {
+                    line,
+                    message: format!(
+                        "filters \"{}\" can never apply: there are no rules of the types it filters",
+                        filter.description
+                    ),
+                });
+            }
+        }
+
+        if let Some(regex) = filter.regex.as_ref() {
+            if regex.is_match("") {
+                problems.push(Problem {
+                    line,
+                    message: format!(
+                        "filters \"{}\" matches empty text and would drop every match",
+                        filter.description
+                    ),
+                });
+            }
+        }
+    }
+
+    let override_lines = section_lines(contents, "overrides");
+    for (i, o) in config.overrides.iter().flatten().enumerate() {
+        if !rules.iter().any(|(_, _, rule)| rule.rule_id() == o.id) && !other_ids.contains(&o.id) {
+            problems.push(Problem {
+                line: override_lines.get(i).cloned(),
+                message: format!("overrides {} doesn't match any rule", o.id),
+            });
+        }
+    }
+
+    for (i, pattern) in patterns.iter().enumerate() {
+        if let Some(regex) = pattern.regex.as_ref() {
+            check_regex(
+                &mut problems,
+                line_of("patterns", i),
+                &format!("patterns \"{}\"", pattern.description),
+                &pattern.pattern,
+                regex,
+            );
+        }
+    }
+
+    for (i, key) in keys.iter().enumerate() {
+        if let Some(regex) = key.key_regex.as_ref() {
+            check_regex(
+                &mut problems,
+                line_of("keys", i),
+                &format!("keys \"{}\" key", key.description),
+                &key.key,
+                regex,
+            );
+        }
+    }
+
+    for (i, file) in files.iter().enumerate() {
+        if !file.enabled.unwrap_or(true) || file.needs_content() {
+            continue;
+        }
+
+        if PROBE_PATHS.iter().all(|path| file.matches_path(path)) {
+            problems.push(Problem {
+                line: line_of("files", i),
+                message: format!(
+                    "files \"{}\" is overly broad: it matches every path",
+                    file.description
+                ),
+            });
+        }
+    }
+
+    problems.sort_by_key(|problem| problem.line);
+    problems
+}
+
+fn on_line(line: Option<usize>) -> String {
+    match line {
+        Some(line) => format!(" on line {}", line),
+        None => String::new(),
+    }
+}
+
+// 1-based lines of each `[[section]]` header, in order
+fn section_lines(contents: &str, section: &str) -> Vec<usize> {
+    let header = format!("[[{}]]", section);
+
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| line.trim_start().starts_with(&header))
+        .map(|(i, _)| i + 1)
+        .collect()
+}
+
+fn check_regex(
+    problems: &mut Vec<Problem>,
+    line: Option<usize>,
+    name: &str,
+    pattern: &str,
+    regex: &Regex,
+) {
+    if regex.is_match("") {
+        problems.push(Problem {
+            line,
+            message: format!("{} is overly broad: it matches empty text", name),
+        });
+    } else if let Some(probe) = PROBE_LINES.iter().find(|probe| regex.is_match(probe)) {
+        problems.push(Problem {
+            line,
+            message: format!("{} is overly broad: it matches \"{}\"", name, probe),
+        });
+    }
+
+    if let Ok(hir) = regex_syntax::parse(pattern) {
+        if has_nested_repetition(&hir, false) {
+            problems.push(Problem {
+                line,
+                message: format!(
+                    "{} nests unbounded repetitions, which backtracks catastrophically in most \
+                     other regex engines",
+                    name
+                ),
+            });
+        }
+    }
+
+    if RegexBuilder::new(regex.as_str())
+        .size_limit(MAX_REGEX_SIZE)
+        .build()
+        .is_err()
+    {
+        problems.push(Problem {
+            line,
+            message: format!("{} compiles to an unusually large program", name),
+        });
+    }
+}
+
+// Looks for things like `(a+)+` or `(\w*\s*)*`
+fn has_nested_repetition(hir: &Hir, in_repetition: bool) -> bool {
+    match hir.kind() {
+        HirKind::Repetition(repetition) => {
+            let unbounded = repetition.max.is_none();
+            if unbounded && in_repetition && can_repeat(&repetition.sub) {
+                return true;
+            }
+
+            has_nested_repetition(&repetition.sub, in_repetition || unbounded)
+        }
+        HirKind::Capture(capture) => has_nested_repetition(&capture.sub, in_repetition),
+        HirKind::Concat(hirs) | HirKind::Alternation(hirs) => hirs
+            .iter()
+            .any(|hir| has_nested_repetition(hir, in_repetition)),
+        _ => false,
+    }
+}
+
+// Whether repeating this expression consumes text, i.e. it isn't just an anchor or empty
+fn can_repeat(hir: &Hir) -> bool {
+    hir.properties().maximum_len() != Some(0)
+}
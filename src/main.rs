@@ -1,48 +1,98 @@
 extern crate clap;
 extern crate csv;
 extern crate regex;
+extern crate regex_syntax;
 extern crate toml;
 #[macro_use]
 extern crate serde_derive;
 extern crate base64;
 extern crate pbr;
+#[macro_use]
 extern crate serde_json;
 #[macro_use(defer)]
 extern crate scopeguard;
+extern crate flate2;
+extern crate globset;
+extern crate tar;
+extern crate encoding_rs;
+extern crate quick_xml;
+extern crate serde;
+extern crate serde_yaml;
+extern crate p12_keystore;
+extern crate sha2;
+extern crate hmac;
+extern crate x509_parser;
+extern crate zip;
 
+mod archive;
+mod baseline;
+mod binary;
+mod credentials;
+mod definitions;
+mod documents;
+mod encoding;
 mod git;
+mod group;
+mod html;
+mod import;
+mod lint;
+mod output;
+mod packs;
+mod redact;
+mod sarif;
+mod signatures;
+mod structured;
+mod suppress;
 
 use base64::encode;
-use clap::{App, Arg};
+use clap::{App, AppSettings, Arg, SubCommand};
 use pbr::ProgressBar;
-use regex::Regex;
-use std::collections::VecDeque;
+use serde::Serializer;
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
 use std::env::current_exe;
 use std::fs::File;
 use std::io::prelude::*;
-use std::io::{stdout, Write};
+use std::io::{stderr, stdout, Write};
 use std::iter::FromIterator;
 use std::path::Path;
 use std::process::exit;
 use std::str;
-use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex, RwLock};
 use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
 use std::vec::Vec;
 
-use git::{ChangeType, Commit, GitClient};
+use archive::{ArchiveLimits, MEMBER_SEPARATOR};
+use baseline::Baseline;
+use definitions::{
+    compile_allowlist, compile_file_patterns, compile_filters, compile_key_patterns,
+    compile_patterns, check_rule_ids, is_selected, Allowlist, Config, FilePattern, Filter,
+    FilterField, FilterTarget, KeyPattern, MatchType, Pattern, Rule, Severity,
+};
+use git::{ChangeType, Commit, FileDiff, GitClient};
+use group::GroupedOutput;
+use output::{OutputFormat, ScanMetadata};
+use redact::Redaction;
+use suppress::{IgnoreFile, IGNORE_FILE};
 
 const NUM_THREADS: usize = 6;
 const MAX_LINE_LENGTH: usize = 5000;
+const DEFAULT_MAX_BINARY_SIZE: u64 = 10;
+// exit codes for a scan, so CI can tell findings apart from a scan that didn't finish
+const EXIT_NO_FINDINGS: i32 = 0;
+const EXIT_FINDINGS: i32 = 1;
+const EXIT_ERROR: i32 = 2;
+// what HEAD is called among branch tips, git doesn't allow a branch with this name
+const HEAD: &str = "HEAD";
 static mut VERBOSE: bool = false;
-static THREAD_DONE_COUNT: AtomicUsize = ATOMIC_USIZE_INIT;
+static THREAD_DONE_COUNT: AtomicUsize = AtomicUsize::new(0);
 
 macro_rules! verbose_print(
     ($($arg:tt)*) => { {
-        let mut v = Default::default();
-        unsafe {
-            v = VERBOSE;
-        }
+        let v = unsafe { VERBOSE };
         if v {
                 let r = writeln!(&mut ::std::io::stdout(), $($arg)*);
                 r.expect("failed printing to stdout");
@@ -50,37 +100,6 @@ macro_rules! verbose_print(
     } }
 );
 
-#[derive(Clone, Serialize, PartialEq, Debug)]
-pub enum MatchType {
-    Pattern,
-    File,
-}
-
-#[derive(Debug, Default, Deserialize)]
-struct Config {
-    patterns: Option<Vec<Pattern>>,
-    filters: Option<Vec<Pattern>>,
-    files: Option<Vec<FilePattern>>,
-}
-
-#[derive(Debug, Default, Serialize, Deserialize, Clone)]
-struct Pattern {
-    description: String,
-    pattern: String,
-    enabled: Option<bool>,
-    case_sensitive: Option<bool>,
-
-    #[serde(skip_deserializing, skip_serializing)]
-    regex: Option<Regex>,
-}
-
-#[derive(Debug, Default, Serialize, Deserialize, Clone)]
-struct FilePattern {
-    description: String,
-    extension: String,
-    binary: Option<bool>,
-}
-
 #[derive(Clone, Serialize)]
 struct PatternMatch {
     description: String,
@@ -92,6 +111,80 @@ struct PatternMatch {
     change_type: ChangeType,
     commit_hash: String,
     commit_date: String,
+
+    // columns below were added later, and stay after the original ones so existing CSV
+    // consumers keep working
+
+    // where in the file the match was found for formats that aren't just lines of text
+    location: String,
+    rule_id: String,
+    // only the text the rule matched, without anything around it
+    secret: String,
+    commit_author: String,
+    commit_message: String,
+    severity: Severity,
+    #[serde(serialize_with = "serialize_list")]
+    tags: Vec<String>,
+    remediation: String,
+    // 1-based line and column (in characters) of the match, if it was found in a line of text
+    line_number: Option<usize>,
+    column: Option<usize>,
+    // identifies the secret wherever it's found, even once it's been redacted
+    fingerprint: String,
+    // whether the secret is still in the tree at the repo's HEAD, or only in its history. Only
+    // known with --branch-tips.
+    present_at_head: Option<bool>,
+    // branches that still have the secret in the tree at their tip
+    #[serde(serialize_with = "serialize_list")]
+    branches: Vec<String>,
+    // the whole line the match was found on
+    #[serde(skip_serializing)]
+    line: String,
+    // the commit's first parent, which removed secrets were last in
+    #[serde(skip_serializing)]
+    parent_hash: Option<String>,
+    // what tells this secret apart when `secret` alone doesn't: a SHA-256 of the whole file for
+    // file rules, or the match with the rest of its value for rules that only find where a
+    // secret starts
+    #[serde(skip_serializing)]
+    identity: Option<String>,
+}
+
+// CSV can't hold lists, so things like tags are written as a single `;` separated column
+fn serialize_list<S: Serializer>(list: &[String], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&list.join(";"))
+}
+
+#[derive(Clone, Default)]
+struct ScanOptions {
+    // None if archives should not be unpacked
+    archive_limits: Option<ArchiveLimits>,
+    // largest binary blob that printable strings will be extracted from, None if binary
+    // blobs should be skipped
+    max_binary_size: Option<u64>,
+}
+
+// Every rule loaded from the definitions file
+#[derive(Clone)]
+struct Rules {
+    patterns: Vec<Pattern>,
+    files: Vec<FilePattern>,
+    keys: Vec<KeyPattern>,
+    allowlist: Allowlist,
+}
+
+// Everything needed to report matches for the commit currently being scanned
+struct ScanContext<'a, F: 'a> {
+    patterns: &'a [Pattern],
+    files: &'a [FilePattern],
+    keys: &'a [KeyPattern],
+    allowlist: &'a Allowlist,
+    // the commit's .sourcesecretsignore
+    ignore: &'a IgnoreFile,
+    options: &'a ScanOptions,
+    on_found: &'a F,
+    repo_path: &'a str,
+    commit: &'a Commit,
 }
 
 fn main() {
@@ -99,6 +192,7 @@ fn main() {
         .version("1.0")
         .author("Lander Brandt <github.com/landaire/sourcesecrets>")
         .about("Searches a git repository for secrets")
+        .setting(AppSettings::SubcommandsNegateReqs)
         .arg(
             Arg::with_name("repos")
                 .value_name("GIT_REPO_PATH")
@@ -109,15 +203,73 @@ fn main() {
             Arg::with_name("definitions")
                 .short("d")
                 .value_name("definitions.toml")
-                .help("File containing pattern definitions")
-                .takes_value(true),
-        ).arg(
+                .help(
+                    "File containing pattern definitions, used along with the built-in rules. \
+                     Files given later override rules from earlier ones.",
+                ).takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        ).arg(rules_arg())
+        .arg(
             Arg::with_name("output_file")
                 .short("o")
                 .value_name("OUTPUT_FILE")
                 .help("File to output data to write results to (use - for stdout)")
                 .takes_value(true)
                 .required(true),
+        ).arg(
+            Arg::with_name("format")
+                .long("format")
+                .value_name("FORMAT")
+                .help("Format to write results in (default csv)")
+                .possible_values(&["csv", "json", "jsonl", "sarif", "html"])
+                .takes_value(true),
+        ).arg(
+            Arg::with_name("group")
+                .long("group")
+                .help(
+                    "Write one result per secret found by each rule instead of one per match, \
+                     with the commits, repos and paths it was found in (csv, json and jsonl only)",
+                ).takes_value(false),
+        ).arg(
+            Arg::with_name("branch_tips")
+                .long("branch-tips")
+                .help(
+                    "Also search the tree at HEAD and each branch tip, to report which secrets are \
+                     still there (results are written once that's done)",
+                ).takes_value(false),
+        ).arg(
+            Arg::with_name("baseline")
+                .long("baseline")
+                .value_name("BASELINE")
+                .help(
+                    "Don't report findings already in this baseline, or in a report written \
+                     with --format json or jsonl",
+                ).takes_value(true),
+        ).arg(
+            Arg::with_name("write_baseline")
+                .long("write-baseline")
+                .help("Add new findings to the --baseline file, creating it if it doesn't exist")
+                .takes_value(false)
+                .requires("baseline"),
+        ).arg(
+            Arg::with_name("redact")
+                .long("redact")
+                .value_name("MODE")
+                .help(
+                    "Hide secrets in the results: full, partial to keep the first and last 4 \
+                     characters (or partial:N for N), or hash for a salted hash",
+                ).takes_value(true),
+        ).arg(
+            Arg::with_name("redact_salt")
+                .long("redact-salt")
+                .value_name("SALT")
+                .help(
+                    "Salt for --redact hashes and fingerprints, so hashes can be compared between \
+                     scans (random by default, when fingerprints are keyed by the repo instead)",
+                )
+                .takes_value(true)
+                .requires("redact"),
         ).arg(
             Arg::with_name("since")
                 .short("s")
@@ -136,66 +288,244 @@ fn main() {
                 .value_name("VERBOSE")
                 .help("Set verbose output (shows results as they come in)")
                 .takes_value(false),
-        ).get_matches();
+        ).arg(
+            Arg::with_name("max_archive_depth")
+                .long("max-archive-depth")
+                .value_name("DEPTH")
+                .help("How many levels of nested archives to unpack (0 disables archive scanning)")
+                .takes_value(true),
+        ).arg(
+            Arg::with_name("max_archive_size")
+                .long("max-archive-size")
+                .value_name("MEGABYTES")
+                .help("Largest archive or archive member to unpack")
+                .takes_value(true),
+        ).arg(
+            Arg::with_name("scan_binary")
+                .long("scan-binary")
+                .help("Extract printable strings from binary files and search them for patterns")
+                .takes_value(false),
+        ).arg(
+            Arg::with_name("max_binary_size")
+                .long("max-binary-size")
+                .value_name("MEGABYTES")
+                .help("Largest binary file to extract strings from (default 10)")
+                .takes_value(true),
+        ).arg(
+            Arg::with_name("fail_on")
+                .long("fail-on")
+                .value_name("SEVERITY")
+                .help(
+                    "Only exit with 1 for findings of at least this severity (info, low, medium, \
+                     high or critical)",
+                ).takes_value(true),
+        ).arg(
+            Arg::with_name("max_findings")
+                .long("max-findings")
+                .value_name("COUNT")
+                .help("Only exit with 1 if there are more than this many findings (default 0)")
+                .takes_value(true),
+        ).arg(
+            Arg::with_name("min_severity")
+                .long("min-severity")
+                .value_name("SEVERITY")
+                .help("Only use rules of at least this severity (info, low, medium, high or critical)")
+                .takes_value(true),
+        ).arg(
+            Arg::with_name("tags")
+                .long("tags")
+                .value_name("TAGS")
+                .help("Only use rules with any of these comma separated tags")
+                .takes_value(true)
+                .use_delimiter(true),
+        ).subcommand(
+            SubCommand::with_name("check-definitions")
+                .about("Checks definitions files for problems, exiting non-zero if any are found")
+                .arg(rules_arg())
+                .arg(
+                    Arg::with_name("definitions")
+                        .value_name("definitions.toml")
+                        .help("Definitions files to check (defaults to the one next to the executable)")
+                        .multiple(true),
+                ),
+        ).subcommand(
+            SubCommand::with_name("test-definitions")
+                .about("Checks each pattern against its should_match and should_not_match examples")
+                .arg(rules_arg())
+                .arg(
+                    Arg::with_name("definitions")
+                        .value_name("definitions.toml")
+                        .help("Definitions files to test (defaults to the one next to the executable)")
+                        .multiple(true),
+                ),
+        ).subcommand(
+            SubCommand::with_name("import-rules")
+                .about("Converts gitleaks or trufflehog rules to definitions, listing anything unsupported")
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .help("Format of the rules file (detected from the file if not given)")
+                        .possible_values(&["gitleaks", "trufflehog"])
+                        .takes_value(true),
+                ).arg(
+                    Arg::with_name("output_file")
+                        .short("o")
+                        .value_name("OUTPUT_FILE")
+                        .help("File to write the definitions to (defaults to stdout)")
+                        .takes_value(true),
+                ).arg(
+                    Arg::with_name("rules_file")
+                        .value_name("RULES_FILE")
+                        .help("gitleaks TOML, trufflehog regexes.json or trufflehog custom detectors YAML")
+                        .required(true),
+                ),
+        ).get_matches_safe()
+        .unwrap_or_else(|e| {
+            // --help and --version aren't errors
+            if !e.use_stderr() {
+                e.exit();
+            }
+            eprintln!("{}", e.message);
+            exit(EXIT_ERROR);
+        });
     unsafe {
         VERBOSE = args.is_present("verbose");
     }
 
+    if let Some(check_args) = args.subcommand_matches("check-definitions") {
+        let (pack_names, paths) = definition_sources(
+            check_args.values_of_lossy("rules"),
+            check_args.values_of_lossy("definitions"),
+        );
+        exit(check_definitions(&pack_names, &paths));
+    }
+
+    if let Some(test_args) = args.subcommand_matches("test-definitions") {
+        let (pack_names, paths) = definition_sources(
+            test_args.values_of_lossy("rules"),
+            test_args.values_of_lossy("definitions"),
+        );
+        exit(test_definitions(&pack_names, &paths));
+    }
+
+    if let Some(import_args) = args.subcommand_matches("import-rules") {
+        exit(import_rules(
+            import_args.value_of("rules_file").unwrap(),
+            import_args.value_of("format"),
+            import_args.value_of("output_file"),
+        ));
+    }
+
     let repos = args.values_of_lossy("repos").unwrap();
 
+    let started_at = unix_time();
+
     let output_file = match args.value_of("output_file").unwrap() {
-        "-" => Box::new(stdout()) as Box<Write>,
-        filename => {
-            Box::new(File::create(filename).expect("Unable to create output file")) as Box<Write>
-        }
+        "-" => Box::new(stdout()) as Box<dyn Write>,
+        filename => Box::new(File::create(filename).unwrap_or_else(|e| {
+            eprintln!("Unable to create output file {}: {}", filename, e);
+            exit(EXIT_ERROR);
+        })) as Box<dyn Write>,
     };
-
-    let definitions_path = match args.value_of("definitions") {
-        Some(p) => p.to_owned(),
-        None => {
-            let mut p = current_exe()
-                .unwrap()
-                .parent()
-                .unwrap()
-                .join("definitions.toml");
-            p.to_str().unwrap().to_owned()
+    let redaction: Option<Redaction> = args.value_of("redact").map(|mode| {
+        let redaction: Redaction = mode.parse().unwrap_or_else(|e| {
+            eprintln!("Invalid --redact: {}", e);
+            exit(EXIT_ERROR);
+        });
+        match args.value_of("redact_salt") {
+            Some(salt) => redaction.with_salt(salt),
+            None => redaction,
         }
+    });
+    let baseline_path = args.value_of("baseline");
+    let write_baseline = args.is_present("write_baseline");
+    let mut baseline = match baseline_path {
+        Some(path) if write_baseline && !Path::new(path).exists() => Baseline::default(),
+        Some(path) => Baseline::load(path).unwrap_or_else(|e| {
+            eprintln!("Could not load baseline {}: {}", path, e);
+            exit(EXIT_ERROR);
+        }),
+        None => Baseline::default(),
     };
+    let output_format: OutputFormat = args
+        .value_of("format")
+        .map(|format| format.parse().unwrap())
+        .unwrap_or(OutputFormat::Csv);
+    let group = args.is_present("group");
+    let branch_tips = args.is_present("branch_tips");
+    if group && (output_format == OutputFormat::Sarif || output_format == OutputFormat::Html) {
+        eprintln!("--group can only be used with csv, json and jsonl, the other formats already group results");
+        exit(EXIT_ERROR);
+    }
 
-    let mut definitions_file = File::open(definitions_path).expect("definitions.toml not found");
-    let mut config_contents = String::new();
-    definitions_file
-        .read_to_string(&mut config_contents)
-        .expect("error while reading definitions file");
+    let mut archive_limits = ArchiveLimits::default();
+    if let Some(depth) = args.value_of("max_archive_depth") {
+        archive_limits.max_depth = number_arg(depth, "--max-archive-depth");
+    }
+    if let Some(size) = args.value_of("max_archive_size") {
+        let size: u64 = number_arg(size, "--max-archive-size");
+        archive_limits.max_size = size * 1024 * 1024;
+        archive_limits.max_total_size =
+            ::std::cmp::max(archive_limits.max_total_size, archive_limits.max_size);
+    }
 
-    let pattern_config = toml::from_str(&config_contents);
+    let max_binary_size: u64 = args
+        .value_of("max_binary_size")
+        .map(|size| number_arg(size, "--max-binary-size"))
+        .unwrap_or(DEFAULT_MAX_BINARY_SIZE);
 
-    if let Err(err) = pattern_config {
-        eprintln!("Error parsing config: {:?}", err);
-        exit(1);
-    }
+    let options = ScanOptions {
+        archive_limits: if archive_limits.max_depth > 0 {
+            Some(archive_limits)
+        } else {
+            None
+        },
+        max_binary_size: if args.is_present("scan_binary") {
+            Some(max_binary_size * 1024 * 1024)
+        } else {
+            None
+        },
+    };
 
-    let pattern_config: Config = pattern_config.unwrap();
-    let mut patterns = pattern_config.patterns.unwrap();
-    let mut filters: Option<Vec<Pattern>> = pattern_config.filters;
-    let mut files = pattern_config.files.unwrap();
+    let min_severity: Severity = match args.value_of("min_severity") {
+        Some(severity) => severity.parse().unwrap_or_else(|e| {
+            eprintln!("Invalid --min-severity: {}", e);
+            exit(EXIT_ERROR);
+        }),
+        None => Severity::Info,
+    };
+    let fail_on: Severity = match args.value_of("fail_on") {
+        Some(severity) => severity.parse().unwrap_or_else(|e| {
+            eprintln!("Invalid --fail-on: {}", e);
+            exit(EXIT_ERROR);
+        }),
+        None => Severity::Info,
+    };
+    let max_findings: usize = args
+        .value_of("max_findings")
+        .map(|count| number_arg(count, "--max-findings"))
+        .unwrap_or(0);
+    let tags = args.values_of_lossy("tags").unwrap_or_default();
 
-    for file in &mut files {
-        file.extension = ".".to_owned() + &file.extension;
-    }
+    let (pack_names, definitions_paths) = definition_sources(
+        args.values_of_lossy("rules"),
+        args.values_of_lossy("definitions"),
+    );
 
-    // loop over all of the patterns to compile their regexes
-    for pattern in &mut patterns {
-        compile_patterns(&mut filters.as_mut().unwrap());
-    }
+    let (mut rules, filters) = load_definitions(&pack_names, &definitions_paths);
 
-    // loop over all of the patterns to compile their regexes
-    if filters.is_some() {
-        compile_patterns(&mut filters.as_mut().unwrap());
-    }
+    rules
+        .patterns
+        .retain(|p| is_selected(p, min_severity, &tags));
+    rules.files.retain(|f| is_selected(f, min_severity, &tags));
+    rules.keys.retain(|k| is_selected(k, min_severity, &tags));
 
     let mut all_commits = Vec::new();
     let mut clients = Vec::new();
+    let mut scanned_repos = Vec::new();
+    // set when something couldn't be scanned, so the results aren't complete
+    let mut scan_failed = false;
 
     // ensure all of the repos exist
     for repo in &repos {
@@ -205,13 +535,14 @@ fn main() {
         // TODO: add test for .git file, not folder, existing in repo path
         if !repo_path.exists() || !repo_path.join(".git").exists() {
             eprintln!("Repo path {} does not exist", repo);
+            scan_failed = true;
             continue;
         }
         verbose_print!("Getting data for repo {}", repo);
 
         let client = Arc::new(GitClient::new(repo.to_string()));
 
-        let mut commits = client
+        let commits = client
             .clone()
             .get_commits(args.value_of("since"), args.value_of("until"));
         all_commits.reserve(commits.len());
@@ -222,40 +553,80 @@ fn main() {
         }
 
         clients.push(client);
+        scanned_repos.push(repo.to_string());
     }
 
+    let mut metadata = ScanMetadata {
+        tool: "sourcesecrets",
+        version: env!("CARGO_PKG_VERSION"),
+        started_at,
+        finished_at: 0,
+        repos: scanned_repos,
+        commits: all_commits.len(),
+        since: args.value_of("since").map(str::to_owned),
+        until: args.value_of("until").map(str::to_owned),
+        rule_packs: pack_names.iter().map(|name| name.to_string()).collect(),
+        definitions: definitions_paths.clone(),
+        rules: rules.patterns.len() + rules.files.len() + rules.keys.len(),
+        redaction: redaction.as_ref().map(|redaction| redaction.to_string()),
+        baseline: baseline_path.map(str::to_owned),
+        findings: 0,
+        suppressed_by_baseline: 0,
+    };
+    let mut writer = {
+        let mut all_rules: Vec<&dyn Rule> = Vec::new();
+        all_rules.extend(rules.patterns.iter().map(|p| p as &dyn Rule));
+        all_rules.extend(rules.files.iter().map(|f| f as &dyn Rule));
+        all_rules.extend(rules.keys.iter().map(|k| k as &dyn Rule));
+        if group {
+            Box::new(GroupedOutput::new(output_format, output_file))
+        } else {
+            output::new_writer(output_format, output_file, &all_rules)
+        }
+    };
+
     let mut threads = Vec::new();
-    // set up the progress bar for all threads + commits
-    let pb = Arc::new(Mutex::new(ProgressBar::new(
-        (all_commits.len() + NUM_THREADS as usize) as u64,
+    // set up the progress bar for all threads + commits, on stderr so results can go to stdout
+    let pb = Arc::new(Mutex::new(ProgressBar::on(
+        stderr(),
+        (all_commits.len() + NUM_THREADS) as u64,
     )));
     let found_matches = Arc::new(RwLock::new(VecDeque::new() as VecDeque<PatternMatch>));
 
     if all_commits.is_empty() {
-        println!("No commits found to search");
-        return;
+        eprintln!("No commits found to search");
+        metadata.finished_at = unix_time();
+        if let Err(e) = writer.finish(&metadata) {
+            eprintln!("Could not write results: {}", e);
+            exit(EXIT_ERROR);
+        }
+        exit(if scan_failed {
+            EXIT_ERROR
+        } else {
+            EXIT_NO_FINDINGS
+        });
     }
 
     let commits_per_thread = all_commits.len() / NUM_THREADS;
     let last_thread_commit_count = commits_per_thread + (all_commits.len() % NUM_THREADS);
     for i in 0..NUM_THREADS {
-        let mut num_commits = if i == NUM_THREADS - 1 {
+        let num_commits = if i == NUM_THREADS - 1 {
             last_thread_commit_count
         } else {
             commits_per_thread
         };
 
         let commits: VecDeque<Commit> = VecDeque::from_iter(all_commits.drain(0..num_commits));
-        let patterns = patterns.clone();
+        let rules = rules.clone();
         let found_matches = found_matches.clone();
         let pb = pb.clone();
-        let files = files.clone();
+        let options = options.clone();
 
         threads.push(thread::spawn(move || {
             pattern_matcher_thread(
                 commits,
-                &patterns,
-                &files,
+                &rules,
+                &options,
                 &pb,
                 move |matched: PatternMatch| {
                     found_matches.write().unwrap().push_back(matched);
@@ -266,24 +637,82 @@ fn main() {
     // this should be empty here -- let's explicitly get rid of this resource
     drop(all_commits);
 
-    let mut csv_writer = csv::Writer::from_writer(output_file);
+    // branch tips are scanned while the threads go through history, their matches wait in the
+    // queue until it's known which are still there
+    let tip_secrets = if branch_tips {
+        Some(scan_tips(&clients, &rules, &options))
+    } else {
+        None
+    };
+
+    // keys for redacted fingerprints when there's no salt to key them with
+    let repo_keys: HashMap<String, String> = match redaction.as_ref() {
+        Some(_) => clients
+            .iter()
+            .map(|client| {
+                (
+                    client.repo_path.clone(),
+                    redact::repo_key(&client.get_root_trees()),
+                )
+            })
+            .collect(),
+        None => HashMap::new(),
+    };
+
     let found_matches = found_matches.clone();
-    while found_matches.read().unwrap().len() > 0
-        || THREAD_DONE_COUNT.load(Ordering::Relaxed) != NUM_THREADS
-    {
+    // findings severe enough to fail the scan
+    let mut failing_findings = 0;
+    loop {
+        // checked before draining the queue so that matches queued just before the last thread
+        // finished are still written
+        let done = THREAD_DONE_COUNT.load(Ordering::SeqCst) == NUM_THREADS;
+
         let mut matches = found_matches.write().unwrap();
         'outer: loop {
             match matches.pop_front() {
-                Some(pattern_match) => {
-                    if pattern_match.match_type == MatchType::Pattern && filters.as_ref().is_some()
-                    {
-                        let filters = filters.as_ref().unwrap();
-                        for filter in filters {
-                            if filter.regex.as_ref().unwrap().is_match(&pattern_match.text) {
-                                continue 'outer;
-                            }
+                Some(mut pattern_match) => {
+                    for filter in &filters {
+                        if is_filtered(filter, &pattern_match) {
+                            continue 'outer;
+                        }
+                    }
+
+                    // filters need to see the secret, so it's only hidden once they've run
+                    let fingerprint = output::fingerprint(&pattern_match);
+                    if let Some(tip_secrets) = tip_secrets.as_ref() {
+                        let tips = tip_secrets
+                            .get(&(pattern_match.repo_path.clone(), fingerprint.clone()));
+                        pattern_match.present_at_head =
+                            Some(tips.is_some_and(|tips| tips.contains(HEAD)));
+                        if let Some(tips) = tips {
+                            pattern_match.branches =
+                                tips.iter().filter(|tip| *tip != HEAD).cloned().collect();
                         }
                     }
+
+                    // redacted results, and baselines written alongside them, only get keyed
+                    // fingerprints
+                    pattern_match.fingerprint = match redaction.as_ref() {
+                        Some(redaction) => redaction.fingerprint(
+                            &fingerprint,
+                            repo_keys
+                                .get(&pattern_match.repo_path)
+                                .map_or("", String::as_str),
+                        ),
+                        None => fingerprint,
+                    };
+                    if baseline.contains(&pattern_match) {
+                        metadata.suppressed_by_baseline += 1;
+                        continue 'outer;
+                    }
+                    if write_baseline {
+                        baseline.insert(&pattern_match);
+                    }
+
+                    if let Some(redaction) = redaction.as_ref() {
+                        redaction.apply(&mut pattern_match);
+                    }
+
                     verbose_print!(
                         "{:?} {} in repo {}",
                         &pattern_match.match_type,
@@ -291,56 +720,500 @@ fn main() {
                         &pattern_match.repo_path
                     );
 
-                    csv_writer
-                        .serialize(pattern_match)
-                        .expect("failed to serialize pattern");
+                    if let Err(e) = writer.write_match(&pattern_match) {
+                        eprintln!("Could not write results: {}", e);
+                        exit(EXIT_ERROR);
+                    }
+                    metadata.findings += 1;
+                    if pattern_match.severity >= fail_on {
+                        failing_findings += 1;
+                    }
                 }
                 None => {
-                    csv_writer.flush().unwrap();
+                    if let Err(e) = writer.flush() {
+                        eprintln!("Could not write results: {}", e);
+                        exit(EXIT_ERROR);
+                    }
                     break;
                 }
             }
         }
+
+        if done {
+            break;
+        }
+    }
+
+    metadata.finished_at = unix_time();
+    if let Err(e) = writer.finish(&metadata) {
+        eprintln!("Could not write results: {}", e);
+        exit(EXIT_ERROR);
+    }
+
+    if write_baseline {
+        let path = baseline_path.unwrap();
+        match baseline.save(path) {
+            Ok(()) => eprintln!("Wrote {} finding(s) to baseline {}", baseline.len(), path),
+            Err(e) => {
+                eprintln!("Could not write baseline {}: {}", path, e);
+                scan_failed = true;
+            }
+        }
     }
 
     for thread in threads {
         if let Err(err) = thread.join() {
             eprintln!("Error joining thread: {:?}", err);
+            scan_failed = true;
         }
     }
+
+    if scan_failed {
+        exit(EXIT_ERROR);
+    }
+
+    if failing_findings > max_findings {
+        eprintln!(
+            "{} finding(s) of at least {} severity, more than the {} allowed",
+            failing_findings,
+            args.value_of("fail_on").unwrap_or("info"),
+            max_findings
+        );
+        exit(EXIT_FINDINGS);
+    }
+
+    exit(EXIT_NO_FINDINGS);
+}
+
+// Parse a number given for an argument, exiting if it isn't one
+fn number_arg<T: str::FromStr>(value: &str, name: &str) -> T {
+    value.parse().unwrap_or_else(|_| {
+        eprintln!("Invalid {}: {} isn't a number", name, value);
+        exit(EXIT_ERROR);
+    })
 }
 
 //
-// Compile regex patterns for a given Pattern struct
+// Load and compile the rules in the given built-in packs and definitions files, exiting if they
+// can't be used
 //
-fn compile_patterns(patterns: &mut [Pattern]) {
-    for mut pattern in patterns {
-        if !pattern.enabled.unwrap_or(true) {
+fn load_definitions(pack_names: &[&str], definitions_paths: &[String]) -> (Rules, Vec<Filter>) {
+    // built-in packs come first so definitions files can override their rules
+    let mut pattern_config = Config::default();
+    for name in pack_names {
+        match toml::from_str(packs::get(name).unwrap()) {
+            Ok(config) => pattern_config.merge(config),
+            Err(err) => {
+                eprintln!("Error parsing built-in {} rules: {}", name, err);
+                exit(EXIT_ERROR);
+            }
+        }
+    }
+
+    for path in definitions_paths {
+        match definitions::load_file(Path::new(path)) {
+            Ok(config) => pattern_config.merge(config),
+            Err(err) => {
+                eprintln!("Error loading definitions: {}", err);
+                exit(EXIT_ERROR);
+            }
+        }
+    }
+
+    for warning in pattern_config.warnings.iter() {
+        eprintln!("{}", warning);
+    }
+
+    for error in pattern_config.apply_overrides() {
+        eprintln!("{}", error);
+    }
+
+    let mut patterns = pattern_config.patterns.unwrap_or_default();
+    let mut filters = pattern_config.filters.unwrap_or_default();
+    let mut files = pattern_config.files.unwrap_or_default();
+    let mut keys = pattern_config.keys.unwrap_or_default();
+    let mut allowlist = pattern_config.allowlist.unwrap_or_default();
+
+    // rules that fail to compile are disabled so the rest can still be used
+    for error in compile_file_patterns(&mut files) {
+        eprintln!("File pattern {}: {}", files[error.index].description, error.message);
+    }
+
+    for error in compile_patterns(&mut patterns) {
+        eprintln!("Pattern {}: {}", patterns[error.index].description, error.message);
+    }
+
+    for error in compile_filters(&mut filters) {
+        eprintln!("Filter {}: {}", filters[error.index].description, error.message);
+    }
+
+    for error in compile_key_patterns(&mut keys) {
+        eprintln!("Key pattern {}: {}", keys[error.index].description, error.message);
+    }
+
+    if let Err(e) = compile_allowlist(&mut allowlist) {
+        eprintln!("Allowlist: {}", e);
+        exit(EXIT_ERROR);
+    }
+
+    let id_errors = {
+        let mut all_rules: Vec<&dyn Rule> = Vec::new();
+        all_rules.extend(patterns.iter().map(|p| p as &dyn Rule));
+        all_rules.extend(files.iter().map(|f| f as &dyn Rule));
+        all_rules.extend(keys.iter().map(|k| k as &dyn Rule));
+        check_rule_ids(&all_rules)
+    };
+    if !id_errors.is_empty() {
+        for error in id_errors {
+            eprintln!("{}", error);
+        }
+        exit(EXIT_ERROR);
+    }
+
+    let rules = Rules {
+        patterns,
+        files,
+        keys,
+        allowlist,
+    };
+
+    (rules, filters)
+}
+
+fn rules_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("rules")
+        .long("rules")
+        .value_name("PACKS")
+        .help(
+            "Built-in rule packs to use, comma separated (generic, cloud, vcs, keys, database, \
+             dotnet, java, node, all or none -- defaults to all)",
+        ).takes_value(true)
+        .use_delimiter(true)
+}
+
+//
+// Work out which built-in packs and definitions files to load. Every pack is used unless some
+// are picked with --rules, and the definitions.toml next to the executable is used if it exists
+// and no other files are given.
+//
+fn definition_sources(
+    rules: Option<Vec<String>>,
+    paths: Option<Vec<String>>,
+) -> (Vec<&'static str>, Vec<String>) {
+    let pack_names = match rules {
+        None => packs::names(),
+        Some(ref rules) if rules.iter().any(|r| r == "all") => packs::names(),
+        Some(ref rules) if rules.iter().all(|r| r == "none") => Vec::new(),
+        Some(rules) => {
+            let mut pack_names = Vec::new();
+            for name in rules {
+                match packs::names().into_iter().find(|pack| *pack == name) {
+                    Some(pack) => pack_names.push(pack),
+                    None => {
+                        eprintln!(
+                            "Unknown rule pack {} (expected one of: {}, all or none)",
+                            name,
+                            packs::names().join(", ")
+                        );
+                        exit(EXIT_ERROR);
+                    }
+                }
+            }
+            pack_names
+        }
+    };
+
+    let paths = paths.unwrap_or_else(|| {
+        let default_path = default_definitions_path();
+        if Path::new(&default_path).exists() {
+            vec![default_path]
+        } else {
+            Vec::new()
+        }
+    });
+
+    if pack_names.is_empty() && paths.is_empty() {
+        eprintln!("No rules to use -- pick some built-in packs with --rules or pass a definitions file");
+        exit(EXIT_ERROR);
+    }
+
+    (pack_names, paths)
+}
+
+//
+// Convert another scanner's rules to a definitions file, reporting what couldn't be converted.
+// Returns the process exit code.
+//
+fn import_rules(path: &str, format: Option<&str>, output_file: Option<&str>) -> i32 {
+    let mut contents = String::new();
+    if let Err(e) = File::open(path).and_then(|mut f| f.read_to_string(&mut contents)) {
+        eprintln!("Could not read {}: {}", path, e);
+        return EXIT_ERROR;
+    }
+
+    let format = match format.map(|format| format.parse()) {
+        Some(format) => format.unwrap(),
+        None => match import::detect(Path::new(path), &contents) {
+            Some(format) => format,
+            None => {
+                eprintln!("Could not tell what kind of rules {} has, pass --format", path);
+                return EXIT_ERROR;
+            }
+        },
+    };
+
+    let config = match import::import(format, &contents) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Error importing {}: {}", path, e);
+            return EXIT_ERROR;
+        }
+    };
+
+    let definitions = match toml::to_string(&config) {
+        Ok(definitions) => format!("# Imported from {}\n\n{}", path, definitions),
+        Err(e) => {
+            eprintln!("Error writing definitions: {}", e);
+            return EXIT_ERROR;
+        }
+    };
+
+    let written = match output_file {
+        Some(output_file) => File::create(output_file).and_then(|mut f| f.write_all(definitions.as_bytes())),
+        None => stdout().write_all(definitions.as_bytes()),
+    };
+    if let Err(e) = written {
+        eprintln!("Could not write definitions: {}", e);
+        return EXIT_ERROR;
+    }
+
+    for warning in config.warnings.iter() {
+        eprintln!("{}", warning);
+    }
+    eprintln!(
+        "Imported {} pattern(s), {} file rule(s) and {} filter(s), {} unsupported",
+        config.patterns.as_ref().map_or(0, Vec::len),
+        config.files.as_ref().map_or(0, Vec::len),
+        config.filters.as_ref().map_or(0, Vec::len),
+        config.warnings.len()
+    );
+
+    0
+}
+
+fn default_definitions_path() -> String {
+    let p = current_exe()
+        .unwrap()
+        .parent()
+        .unwrap()
+        .join("definitions.toml");
+    p.to_str().unwrap().to_owned()
+}
+
+//
+// Lint each definitions file, printing any problems found. Returns the process exit code.
+//
+fn check_definitions(pack_names: &[&str], paths: &[String]) -> i32 {
+    let mut problem_count = 0;
+
+    let mut sources = Vec::new();
+    for name in pack_names {
+        sources.push((
+            format!("{} (built-in)", name),
+            packs::get(name).unwrap().to_owned(),
+            true,
+        ));
+    }
+
+    for path in paths {
+        let mut contents = String::new();
+        if let Err(e) = File::open(path).and_then(|mut f| f.read_to_string(&mut contents)) {
+            println!("{}: could not read definitions: {}", path, e);
+            problem_count += 1;
+            continue;
+        }
+
+        // other scanners' rules are checked after converting them, so lines aren't meaningful
+        if import::detect(Path::new(path), &contents).is_some() {
+            let converted = definitions::parse_file(Path::new(path), &contents)
+                .and_then(|config| toml::to_string(&config).map_err(|e| e.to_string()));
+            match converted {
+                Ok(converted) => sources.push((path.clone(), converted, false)),
+                Err(e) => {
+                    println!("{}: {}", path, e);
+                    problem_count += 1;
+                }
+            }
             continue;
         }
 
-        if !pattern.case_sensitive.unwrap_or(false) {
-            pattern.pattern = "(?i)".to_owned() + &pattern.pattern;
+        sources.push((path.clone(), contents, true));
+    }
+
+    // filters and overrides can refer to rules from the other sources and included files
+    let mut config = Config::default();
+    for name in pack_names {
+        if let Ok(pack) = toml::from_str(packs::get(name).unwrap()) {
+            config.merge(pack);
         }
+    }
+    for (path, contents, _) in sources.iter().skip(pack_names.len()) {
+        match definitions::load_file(Path::new(path)) {
+            Ok(loaded) => config.merge(loaded),
+            // only the file's includes, its own problems are reported below
+            Err(e) if toml::from_str::<Config>(contents).is_ok() => {
+                println!("{}: {}", path, e);
+                problem_count += 1;
+            }
+            Err(_) => {}
+        }
+    }
 
-        pattern.regex = match Regex::new(&pattern.pattern) {
-            Ok(r) => Some(r),
-            Err(e) => {
-                eprintln!(
-                    "Could not compile pattern {}: {}",
-                    pattern.description.clone(),
-                    e
+    let mut ids = HashSet::new();
+    ids.extend(config.patterns.iter().flatten().map(|p| p.rule_id().to_owned()));
+    ids.extend(config.files.iter().flatten().map(|f| f.rule_id().to_owned()));
+    ids.extend(config.keys.iter().flatten().map(|k| k.rule_id().to_owned()));
+
+    for (path, contents, has_lines) in sources {
+        for problem in lint::check_definitions(&contents, &ids) {
+            match problem.line.filter(|_| has_lines) {
+                Some(line) => println!("{}:{}: {}", path, line, problem.message),
+                None => println!("{}: {}", path, problem.message),
+            }
+            problem_count += 1;
+        }
+    }
+
+    if problem_count > 0 {
+        eprintln!("{} problem(s) found", problem_count);
+        1
+    } else {
+        0
+    }
+}
+
+//
+// Run each pattern's examples through the same matching and filtering used while scanning,
+// printing the ones that don't behave as expected. Returns the process exit code.
+//
+fn test_definitions(pack_names: &[&str], paths: &[String]) -> i32 {
+    let mut example_count = 0;
+    let mut failure_count = 0;
+
+    // rules are tested together since filters from one file can drop matches from another
+    let (mut rules, filters) = load_definitions(pack_names, paths);
+
+    // examples are checked regardless of which paths their pattern is limited to
+    for pattern in &mut rules.patterns {
+        pattern.path_globs = None;
+        pattern.exclude_globs = None;
+    }
+
+    let commit = Commit {
+        hash: String::new(),
+        parent: None,
+        date: String::new(),
+        author: String::new(),
+        subject: String::new(),
+        client: None,
+    };
+    let options = ScanOptions::default();
+    let ignore = IgnoreFile::default();
+    let found = RefCell::new(Vec::new());
+    let on_found = |matched: PatternMatch| found.borrow_mut().push(matched);
+    let ctx = ScanContext {
+        patterns: &rules.patterns,
+        files: &rules.files,
+        keys: &rules.keys,
+        allowlist: &rules.allowlist,
+        ignore: &ignore,
+        options: &options,
+        on_found: &on_found,
+        repo_path: "",
+        commit: &commit,
+    };
+
+    for pattern in &rules.patterns {
+        if !pattern.enabled.unwrap_or(true) {
+            continue;
+        }
+
+        let examples = pattern
+            .should_match
+            .iter()
+            .flatten()
+            .map(|example| (example, true))
+            .chain(
+                pattern
+                    .should_not_match
+                    .iter()
+                    .flatten()
+                    .map(|example| (example, false)),
+            );
+
+        for (example, should_match) in examples {
+            example_count += 1;
+
+            found.borrow_mut().clear();
+            // diff lines are trimmed before they're searched
+            check_patterns(&ctx, example.trim(), None, ChangeType::Addition, "example.txt", "", None);
+
+            let matched = found.borrow().iter().any(|m| {
+                m.rule_id == pattern.rule_id() && !filters.iter().any(|f| is_filtered(f, m))
+            });
+            if matched != should_match {
+                failure_count += 1;
+                println!(
+                    "{} should{} match \"{}\"",
+                    pattern.rule_id(),
+                    if should_match { "" } else { " not" },
+                    example
                 );
-                None
             }
-        };
+        }
+    }
+
+    println!("{} example(s), {} failure(s)", example_count, failure_count);
+
+    if failure_count > 0 {
+        1
+    } else {
+        0
     }
 }
 
+//
+// Determine if a filter drops a match
+//
+fn is_filtered(filter: &Filter, pattern_match: &PatternMatch) -> bool {
+    if !filter.applies_to(&pattern_match.rule_id, pattern_match.match_type) {
+        return false;
+    }
+
+    let regex = match filter.regex.as_ref() {
+        Some(regex) => regex,
+        None => return false,
+    };
+
+    filter.fields().iter().any(|field| {
+        let value = match (field, filter.target()) {
+            (FilterField::Text, FilterTarget::Text) => &pattern_match.text,
+            (FilterField::Text, FilterTarget::Secret) => &pattern_match.secret,
+            (FilterField::Text, FilterTarget::Line) => &pattern_match.line,
+            (FilterField::File, _) => &pattern_match.file,
+            (FilterField::Commit, _) => &pattern_match.commit_hash,
+            (FilterField::Message, _) => &pattern_match.commit_message,
+            (FilterField::Author, _) => &pattern_match.commit_author,
+        };
+
+        regex.is_match(value)
+    })
+}
+
 fn pattern_matcher_thread<F, T>(
     mut commits: VecDeque<Commit>,
-    patterns: &[Pattern],
-    files: &[FilePattern],
+    rules: &Rules,
+    options: &ScanOptions,
     pb: &Arc<Mutex<ProgressBar<T>>>,
     on_found: F,
 ) where
@@ -356,181 +1229,724 @@ fn pattern_matcher_thread<F, T>(
         THREAD_DONE_COUNT.fetch_add(1, Ordering::SeqCst);
     });
 
-    let mut in_file = false;
-    let mut file_info: Option<FilePattern> = None;
-    let mut file_name: Option<String> = None;
-    let mut file_index: Option<String> = None;
+    while let Some(commit) = commits.pop_front() {
+        let client = commit.client.as_ref().unwrap();
+        let mut pb = pb.lock().unwrap();
+        pb.inc();
+        drop(pb);
 
-    loop {
-        let commit = commits.pop_front();
-
-        match commit {
-            Some(commit) => {
-                let client = commit.client.as_ref().unwrap();
-                let mut pb = pb.lock().unwrap();
-                pb.inc();
-                drop(pb);
-
-                let content = client.get_commit_content(&commit);
-                in_file = false;
-                file_info = None;
-                file_name = None;
-                file_index = None;
-
-                'outer: for line in str::lines(&content) {
-                    let line = line.trim();
-
-                    // ignore @@ lines since that just tells you the line range
-                    // and that we're on a new file boundary
-                    if line.starts_with("diff --git") {
-                        // it does, so now let's parse it out
-                        // NOTE: this could easily be broken by paths with spaces...
-                        // we're going to assume that the repos do not contain any folder
-                        // ending with " b/"
-
-                        // 11 is the length of "git --diff a/"
-                        file_name = Some(line.chars().skip(13).collect());
-                        file_name =
-                            Some(file_name.unwrap().split(" b/").next().unwrap().to_string());
-
-                        for file in files {
-                            // just check if the line contains the extension first
-                            if line.contains(&file.extension)
-                                && file_name.as_ref().unwrap().ends_with(&file.extension)
-                            {
-                                in_file = true;
-                                file_info = Some(file.clone());
-                                continue 'outer;
-                            }
-                        }
+        let ignore = read_ignore_file(client, &commit.hash);
+        let report = |pattern_match: PatternMatch| {
+            if !ignore.is_ignored_fingerprint(&output::fingerprint(&pattern_match)) {
+                on_found(pattern_match);
+            }
+        };
+        let ctx = ScanContext {
+            patterns: &rules.patterns,
+            files: &rules.files,
+            keys: &rules.keys,
+            allowlist: &rules.allowlist,
+            ignore: &ignore,
+            options,
+            on_found: &report,
+            repo_path: &client.repo_path,
+            commit: &commit,
+        };
 
-                        file_index = None;
-                        continue;
-                    }
+        for diff in client.get_commit_diff(&commit) {
+            scan_file_diff(&ctx, client, &diff);
+        }
+    }
+}
 
-                    if in_file {
-                        if line.starts_with("index ") {
-                            file_index = Some(line.split("..").skip(1).take(1).collect());
-                            continue;
-                        }
+fn read_ignore_file(client: &GitClient, commit: &str) -> IgnoreFile {
+    let contents = client.get_file_at_commit(commit, Some(&IGNORE_FILE.to_owned()));
+    IgnoreFile::parse(&String::from_utf8_lossy(&contents))
+}
 
-                        if file_index.is_some() {
-                            // we're in a file that we have a pattern for -- we need to get its
-                            // contents now
-                            let mut file_data =
-                                client.get_file_at_commit(file_index.as_ref().unwrap(), None);
-                            if file_data.is_empty() {
-                                file_data = client.get_file_at_commit(
-                                    &commit.hash,
-                                    Some(file_name.as_ref().unwrap()),
-                                );
-                            }
-
-                            let file_data_string: String =
-                                if file_info.as_ref().unwrap().binary.unwrap_or(false) {
-                                    // if it's a binary file we need to encode as base64
-                                    encode(&file_data.as_slice())
-                                } else {
-                                    String::from_utf8_lossy(&file_data).into_owned()
-                                };
-
-                            let fname = file_name.as_ref().unwrap().clone();
-                            let matched = PatternMatch {
-                                description: file_info.as_ref().unwrap().description.clone(),
-                                text: file_data_string,
-                                match_type: MatchType::File,
-                                repo_path: client.repo_path.clone(),
-                                full_path: Path::new(&client.repo_path)
-                                    .join(&fname)
-                                    .into_os_string()
-                                    .into_string()
-                                    .unwrap(),
-                                file: fname,
-                                change_type: ChangeType::Unknown,
-                                commit_hash: commit.hash.clone(),
-                                commit_date: commit.date.clone(),
-                            };
-                            on_found(matched);
-
-                            in_file = false;
-                        }
+fn scan_file_diff<F>(ctx: &ScanContext<F>, client: &GitClient, diff: &FileDiff)
+where
+    F: Fn(PatternMatch),
+{
+    let file_name = &diff.file_name;
+    if ctx.allowlist.is_allowed(file_name) || ctx.ignore.is_ignored_path(file_name) {
+        return;
+    }
 
-                        continue;
-                    }
+    let mut file_info = find_file_pattern(ctx.files, file_name, None);
+    let mut new_data: Option<Vec<u8>> = None;
 
-                    if line.len() > MAX_LINE_LENGTH {
-                        verbose_print!("Skipping line -- too long");
-                        continue;
-                    }
+    // rules that look at a file's contents are only checked against files the commit added or
+    // changed, since fetching every blob is expensive
+    if file_info.is_none() {
+        if let Some(blob) = diff.new_blob.as_ref() {
+            let needs_content = ctx
+                .files
+                .iter()
+                .any(|file| file.needs_content() && file.matches_path(file_name));
+            if needs_content {
+                let data = client.get_file_at_commit(blob, None);
+                file_info = find_file_pattern(ctx.files, file_name, Some(&data));
+                new_data = Some(data);
+            }
+        }
+    }
 
-                    check_patterns(
-                        &patterns,
-                        &line,
-                        &on_found,
-                        &client.repo_path,
-                        file_name.as_ref().unwrap(),
-                        &commit,
-                    );
+    // a file rule matching doesn't stop the file's contents from being searched as well, since
+    // e.g. a config file with a private key in it can have other secrets in it too
+    if let Some(file_info) = file_info.filter(|_| diff.has_index()) {
+        // we're in a file that we have a pattern for -- we need to get its
+        // contents now
+        let mut file_data = match (new_data, diff.new_blob.as_ref()) {
+            (Some(data), _) => data,
+            (None, Some(blob)) => client.get_file_at_commit(blob, None),
+            (None, None) => Vec::new(),
+        };
+        if file_data.is_empty() {
+            file_data = client.get_file_at_commit(&ctx.commit.hash, Some(file_name));
+        }
+
+        (ctx.on_found)(file_match(ctx, file_info, &file_data, file_name));
+    }
+
+    // notebooks are diffed as JSON and Office documents as binary, so both need to have their
+    // text extracted before we can search them
+    if documents::is_document(file_name) {
+        scan_document_diff(ctx, client, diff);
+        return;
+    }
+
+    let is_archive = ctx.options.archive_limits.is_some() && archive::is_archive(file_name);
+
+    // git treats UTF-16 text as binary, so binary files need to be fetched even if we aren't
+    // extracting strings from them to see if they're really text
+    if is_archive || diff.binary {
+        // scan whatever version of the file this commit leaves behind, or the one it
+        // removed if the file was deleted
+        let (blob, change_type) = match (diff.new_blob.as_ref(), diff.old_blob.as_ref()) {
+            (Some(blob), _) => (blob, ChangeType::Addition),
+            (None, Some(blob)) => (blob, ChangeType::Removal),
+            (None, None) => return,
+        };
+
+        let data = client.get_file_at_commit(blob, None);
+        if is_archive {
+            scan_archive(ctx, &data, change_type, file_name);
+        } else {
+            scan_blob(ctx, &data, change_type, file_name);
+        }
+        return;
+    }
+
+    if !ctx.keys.is_empty() {
+        if let Some(format) = structured::format_for(file_name) {
+            scan_structured_diff(ctx, client, diff, format);
+        }
+    }
+
+    for line in &diff.lines {
+        if line.text.len() > MAX_LINE_LENGTH {
+            verbose_print!("Skipping line -- too long");
+            continue;
+        }
+
+        check_patterns(
+            ctx,
+            &line.text,
+            line.previous.as_deref(),
+            line.change_type,
+            file_name,
+            "",
+            Some((line.number, line.column)),
+        );
+    }
+}
+
+//
+// Find which branch tips in each repo still have each secret in their tree, by repo path and
+// fingerprint. HEAD is included as a tip too since it may not be on a branch. Blobs that are the
+// same on several branches, or at several paths, are only read once, and each tip's
+// .sourcesecretsignore is applied to what's found in it.
+//
+fn scan_tips(
+    clients: &[Arc<GitClient>],
+    rules: &Rules,
+    options: &ScanOptions,
+) -> HashMap<(String, String), BTreeSet<String>> {
+    let mut found: HashMap<(String, String), BTreeSet<String>> = HashMap::new();
+
+    for client in clients {
+        let mut tips = client.get_branches();
+        if let Some(hash) = client.resolve(HEAD) {
+            tips.push((HEAD.to_owned(), hash));
+        }
+
+        // which paths each blob is at, and which tips have it at each of them
+        let mut blobs: HashMap<String, HashMap<String, Vec<&str>>> = HashMap::new();
+        let mut ignores: HashMap<&str, IgnoreFile> = HashMap::new();
+        for (name, hash) in &tips {
+            verbose_print!("Scanning {} ({}) of repo {}", name, hash, client.repo_path);
+            let ignore = read_ignore_file(client, hash);
+            for file in client.get_tree(hash) {
+                if !rules.allowlist.is_allowed(&file.path) && !ignore.is_ignored_path(&file.path) {
+                    blobs
+                        .entry(file.blob)
+                        .or_default()
+                        .entry(file.path)
+                        .or_default()
+                        .push(name);
+                }
+            }
+            ignores.insert(name, ignore);
+        }
+
+        // ignore files differ between tips, so they're applied to each tip afterwards
+        let no_ignore = IgnoreFile::default();
+        // matches are only used for their fingerprints, so they don't need a real commit
+        let commit = Commit {
+            hash: String::new(),
+            parent: None,
+            date: String::new(),
+            author: String::new(),
+            subject: String::new(),
+            client: None,
+        };
+        for (blob, paths) in blobs {
+            let data = client.get_file_at_commit(&blob, None);
+
+            // rules can depend on the path, so the blob is searched once for each path it's at
+            for (path, names) in paths {
+                let fingerprints = RefCell::new(HashSet::new());
+                let on_found = |pattern_match: PatternMatch| {
+                    fingerprints
+                        .borrow_mut()
+                        .insert(output::fingerprint(&pattern_match));
+                };
+                let ctx = ScanContext {
+                    patterns: &rules.patterns,
+                    files: &rules.files,
+                    keys: &rules.keys,
+                    allowlist: &rules.allowlist,
+                    ignore: &no_ignore,
+                    options,
+                    on_found: &on_found,
+                    repo_path: &client.repo_path,
+                    commit: &commit,
+                };
+                scan_tree_file(&ctx, &data, &path);
+
+                for fingerprint in fingerprints.into_inner() {
+                    let tips: Vec<String> = names
+                        .iter()
+                        .filter(|name| !ignores[*name].is_ignored_fingerprint(&fingerprint))
+                        .map(|name| name.to_string())
+                        .collect();
+                    found
+                        .entry((client.repo_path.clone(), fingerprint))
+                        .or_default()
+                        .extend(tips);
                 }
             }
-            _ => break,
         }
     }
+
+    found
 }
 
-fn check_patterns<F>(
-    patterns: &[Pattern],
-    line: &str,
-    on_found: &F,
-    repo_path: &str,
+//
+// Search the whole of a file in a commit's tree, the same way files are searched when a commit
+// adds them
+//
+fn scan_tree_file<F>(ctx: &ScanContext<F>, data: &[u8], path: &str)
+where
+    F: Fn(PatternMatch),
+{
+    if let Some(file_info) = find_file_pattern(ctx.files, path, Some(data)) {
+        (ctx.on_found)(file_match(ctx, file_info, data, path));
+    }
+
+    if ctx.options.archive_limits.is_some() && archive::is_archive(path) {
+        scan_archive(ctx, data, ChangeType::Addition, path);
+    } else {
+        scan_blob(ctx, data, ChangeType::Addition, path);
+    }
+}
+
+fn scan_archive<F>(ctx: &ScanContext<F>, data: &[u8], change_type: ChangeType, file_name: &str)
+where
+    F: Fn(PatternMatch),
+{
+    let limits = ctx.options.archive_limits.as_ref().unwrap();
+    let extracted = match archive::extract(file_name, data, limits) {
+        Ok(extracted) => extracted,
+        Err(e) => {
+            eprintln!(
+                "Skipping archive {} in commit {}: {}",
+                file_name, ctx.commit.hash, e
+            );
+            return;
+        }
+    };
+
+    for (path, reason) in extracted.skipped {
+        eprintln!(
+            "Skipping {}{}{} in commit {}: {}",
+            file_name, MEMBER_SEPARATOR, path, ctx.commit.hash, reason
+        );
+    }
+
+    for entry in extracted.entries {
+        let member_name = format!("{}{}{}", file_name, MEMBER_SEPARATOR, entry.path);
+        if ctx.allowlist.is_allowed(&member_name) || ctx.ignore.is_ignored_path(&member_name) {
+            continue;
+        }
+
+        if let Some(file_info) = find_file_pattern(ctx.files, &entry.path, Some(&entry.data)) {
+            (ctx.on_found)(file_match(ctx, file_info, &entry.data, &member_name));
+        }
+
+        // nested archives have already been expanded into their own entries
+        if archive::is_archive(&entry.path) {
+            continue;
+        }
+
+        scan_blob(ctx, &entry.data, change_type, &member_name);
+    }
+}
+
+//
+// Search the full contents of a file for patterns. Files that can't be decoded as text only
+// have their printable strings searched, and only if binary scanning is enabled.
+//
+fn scan_blob<F>(ctx: &ScanContext<F>, data: &[u8], change_type: ChangeType, file_name: &str)
+where
+    F: Fn(PatternMatch),
+{
+    if documents::is_document(file_name) {
+        let lines = extract_document(ctx, data, file_name);
+        for (i, line) in lines.iter().enumerate() {
+            let previous = previous_text(&lines, i);
+            check_patterns(ctx, &line.text, previous, change_type, file_name, &line.location, None);
+        }
+        return;
+    }
+
+    // only lines of decoded text have meaningful line numbers
+    let (lines, numbered): (Vec<String>, bool) = if let Some(text) = encoding::decode(data) {
+        if !ctx.keys.is_empty() {
+            if let Some(format) = structured::format_for(file_name) {
+                let entries = parse_structured(&text, format, file_name);
+                check_keys(ctx, &entries, &text, change_type, file_name);
+            }
+        }
+
+        (str::lines(&text).map(|line| line.to_string()).collect(), true)
+    } else {
+        match ctx.options.max_binary_size {
+            Some(max_size) if data.len() as u64 <= max_size => {
+                (binary::extract_strings(data, binary::MIN_STRING_LENGTH), false)
+            }
+            Some(_) => {
+                verbose_print!("Skipping binary file {} -- too large", file_name);
+                return;
+            }
+            None => return,
+        }
+    };
+
+    for (i, line) in lines.iter().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.len() > MAX_LINE_LENGTH {
+            continue;
+        }
+
+        let indent = line.len() - line.trim_start().len();
+        let position = if numbered {
+            Some((i + 1, line[..indent].chars().count() + 1))
+        } else {
+            None
+        };
+        let previous = if i > 0 { Some(lines[i - 1].trim()) } else { None };
+        check_patterns(ctx, trimmed, previous, change_type, file_name, "", position);
+    }
+}
+
+fn extract_document<F>(
+    ctx: &ScanContext<F>,
+    data: &[u8],
     file_name: &str,
-    commit: &Commit,
+) -> Vec<documents::DocumentLine> {
+    // documents are zip files too, so they get the same limits as archives
+    let limits = ctx.options.archive_limits.clone().unwrap_or_default();
+
+    match documents::extract(file_name, data, &limits) {
+        Ok(lines) => lines
+            .into_iter()
+            .filter(|line| line.text.len() <= MAX_LINE_LENGTH)
+            .collect(),
+        Err(e) => {
+            verbose_print!("Could not extract text from {}: {}", file_name, e);
+            Vec::new()
+        }
+    }
+}
+
+//
+// Compare the text of a document before and after a commit so that only the lines the commit
+// added or removed are reported
+//
+fn scan_document_diff<F>(ctx: &ScanContext<F>, client: &GitClient, diff: &FileDiff)
+where
+    F: Fn(PatternMatch),
+{
+    let read_lines = |blob: Option<&String>| match blob {
+        Some(blob) => extract_document(ctx, &client.get_file_at_commit(blob, None), &diff.file_name),
+        None => Vec::new(),
+    };
+
+    let old_lines = read_lines(diff.old_blob.as_ref());
+    let new_lines = read_lines(diff.new_blob.as_ref());
+
+    // compare by text alone since inserting a cell or paragraph shifts the location of
+    // everything after it
+    let old_text: HashSet<&str> = old_lines.iter().map(|l| l.text.as_str()).collect();
+    let new_text: HashSet<&str> = new_lines.iter().map(|l| l.text.as_str()).collect();
+
+    for (i, line) in new_lines.iter().enumerate() {
+        if !old_text.contains(line.text.as_str()) {
+            let previous = previous_text(&new_lines, i);
+            check_patterns(ctx, &line.text, previous, ChangeType::Addition, &diff.file_name, &line.location, None);
+        }
+    }
+
+    for (i, line) in old_lines.iter().enumerate() {
+        if !new_text.contains(line.text.as_str()) {
+            let previous = previous_text(&old_lines, i);
+            check_patterns(ctx, &line.text, previous, ChangeType::Removal, &diff.file_name, &line.location, None);
+        }
+    }
+}
+
+fn previous_text(lines: &[documents::DocumentLine], i: usize) -> Option<&str> {
+    i.checked_sub(1).map(|i| lines[i].text.as_str())
+}
+
+//
+// Compare the keys in a structured file before and after a commit so that only the settings
+// the commit added or removed are reported
+//
+fn scan_structured_diff<F>(
+    ctx: &ScanContext<F>,
+    client: &GitClient,
+    diff: &FileDiff,
+    format: structured::Format,
 ) where
     F: Fn(PatternMatch),
 {
-    for pattern in patterns {
-        if !pattern.enabled.unwrap_or(true) {
+    let read_text = |blob: Option<&String>| {
+        blob.and_then(|blob| encoding::decode(&client.get_file_at_commit(blob, None)))
+    };
+    let old_text = read_text(diff.old_blob.as_ref()).unwrap_or_default();
+    let new_text = read_text(diff.new_blob.as_ref()).unwrap_or_default();
+
+    // a file that was added or deleted has nothing to parse on one side
+    let read_entries = |text: &str| {
+        if text.is_empty() {
+            Vec::new()
+        } else {
+            parse_structured(text, format, &diff.file_name)
+        }
+    };
+    let old_entries = read_entries(&old_text);
+    let new_entries = read_entries(&new_text);
+
+    let entry_set = |entries: &[structured::Entry]| -> HashSet<(String, String)> {
+        entries
+            .iter()
+            .map(|e| (e.path.clone(), e.value.clone()))
+            .collect()
+    };
+    let old_set = entry_set(&old_entries);
+    let new_set = entry_set(&new_entries);
+
+    let added: Vec<structured::Entry> = new_entries
+        .into_iter()
+        .filter(|e| !old_set.contains(&(e.path.clone(), e.value.clone())))
+        .collect();
+    let removed: Vec<structured::Entry> = old_entries
+        .into_iter()
+        .filter(|e| !new_set.contains(&(e.path.clone(), e.value.clone())))
+        .collect();
+
+    check_keys(ctx, &added, &new_text, ChangeType::Addition, &diff.file_name);
+    check_keys(ctx, &removed, &old_text, ChangeType::Removal, &diff.file_name);
+}
+
+fn parse_structured(text: &str, format: structured::Format, file_name: &str) -> Vec<structured::Entry> {
+    match structured::parse(format, text) {
+        Ok(entries) => entries,
+        Err(e) => {
+            verbose_print!("Could not parse {} as {:?}: {}", file_name, format, e);
+            Vec::new()
+        }
+    }
+}
+
+fn check_keys<F>(
+    ctx: &ScanContext<F>,
+    entries: &[structured::Entry],
+    // the text the entries were parsed from, for `sourcesecrets:allow` markers
+    text: &str,
+    change_type: ChangeType,
+    file_name: &str,
+) where
+    F: Fn(PatternMatch),
+{
+    for entry in entries {
+        let value = entry.value.trim();
+        if value.is_empty() || value.len() > MAX_LINE_LENGTH {
             continue;
         }
 
-        if pattern.regex.as_ref().unwrap().is_match(&line) {
-            let mat = pattern.regex.as_ref().unwrap().find(&line).unwrap();
-            let matched_string: String = line
-                .chars()
-                .skip(mat.start() - 1)
-                .take(mat.end() - 1)
-                .collect();
+        for pattern in ctx.keys {
+            if !pattern.enabled.unwrap_or(true) {
+                continue;
+            }
+
+            let key_regex = match pattern.key_regex.as_ref() {
+                Some(regex) => regex,
+                None => continue,
+            };
+
+            if !key_regex.is_match(&entry.key) {
+                continue;
+            }
 
-            let change_type = match line.chars().next().unwrap() {
-                '+' => ChangeType::Addition,
-                '-' => ChangeType::Removal,
-                '@' => {
+            if let Some(value_regex) = pattern.value_regex.as_ref() {
+                if !value_regex.is_match(value) {
                     continue;
                 }
-                unknown_type => {
-                    eprintln!("Unexpected value for change type: {:?}", unknown_type);
+            }
+
+            if !pattern.allow_placeholders.unwrap_or(false) && structured::is_placeholder(value) {
+                continue;
+            }
+
+            if let Some((line, previous)) = structured::find_line(text, entry) {
+                if suppress::is_allowed_inline(line, previous, pattern.rule_id()) {
                     continue;
                 }
-            };
+            }
 
-            let matched_text: String = matched_string.chars().skip(1).collect();
-            let matched = PatternMatch {
-                description: pattern.description.clone(),
-                text: matched_text.trim().to_owned(),
-                match_type: MatchType::Pattern,
-                repo_path: repo_path.to_string(),
-                file: file_name.to_owned(),
-                full_path: Path::new(&repo_path)
-                    .join(file_name)
-                    .into_os_string()
-                    .into_string()
-                    .unwrap(),
+            (ctx.on_found)(PatternMatch {
+                text: value.to_owned(),
+                secret: value.to_owned(),
+                line: format!("{}={}", entry.key, value),
+                location: entry.path.clone(),
                 change_type,
-                commit_hash: commit.hash.clone(),
-                commit_date: commit.date.clone(),
-            };
+                ..new_match(ctx, pattern, MatchType::Key, file_name)
+            });
+        }
+    }
+}
+
+//
+// Build a match for a rule with everything but what was matched and where filled in
+//
+fn new_match<F>(
+    ctx: &ScanContext<F>,
+    rule: &dyn Rule,
+    match_type: MatchType,
+    file_name: &str,
+) -> PatternMatch {
+    PatternMatch {
+        description: rule.description().to_owned(),
+        rule_id: rule.rule_id().to_owned(),
+        severity: rule.severity(),
+        tags: rule.tags().to_vec(),
+        remediation: rule.remediation().unwrap_or_default().to_owned(),
+        text: String::new(),
+        secret: String::new(),
+        fingerprint: String::new(),
+        line: String::new(),
+        repo_path: ctx.repo_path.to_string(),
+        file: file_name.to_owned(),
+        full_path: Path::new(ctx.repo_path)
+            .join(file_name)
+            .into_os_string()
+            .into_string()
+            .unwrap(),
+        location: String::new(),
+        line_number: None,
+        column: None,
+        match_type,
+        change_type: ChangeType::Unknown,
+        commit_hash: ctx.commit.hash.clone(),
+        parent_hash: ctx.commit.parent.clone(),
+        commit_date: ctx.commit.date.clone(),
+        commit_author: ctx.commit.author.clone(),
+        commit_message: ctx.commit.subject.clone(),
+        present_at_head: None,
+        branches: Vec::new(),
+        identity: None,
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or(0)
+}
+
+//
+// Shannon entropy of some text in bits per character
+//
+fn entropy(text: &str) -> f64 {
+    let mut counts = HashMap::new();
+    let mut total = 0;
+    for c in text.chars() {
+        *counts.entry(c).or_insert(0) += 1;
+        total += 1;
+    }
+
+    counts
+        .values()
+        .map(|&count| {
+            let p = f64::from(count) / f64::from(total);
+            -p * p.log2()
+        })
+        .sum()
+}
+
+fn find_file_pattern<'a>(
+    files: &'a [FilePattern],
+    file_name: &str,
+    data: Option<&[u8]>,
+) -> Option<&'a FilePattern> {
+    files.iter().find(|file| file.is_match(file_name, data))
+}
 
-            on_found(matched);
+fn file_match<F>(
+    ctx: &ScanContext<F>,
+    file_info: &FilePattern,
+    file_data: &[u8],
+    file_name: &str,
+) -> PatternMatch {
+    // report what's in credential containers rather than dumping their contents
+    let file_data_string: String = if let Some(info) = credentials::describe(file_name, file_data)
+    {
+        info.to_string()
+    } else if file_info.binary.unwrap_or(false) {
+        // if it's a binary file we need to encode as base64
+        encode(file_data)
+    } else {
+        encoding::decode(file_data)
+            .unwrap_or_else(|| String::from_utf8_lossy(file_data).into_owned())
+    };
+
+    // PEM files can hold certificates and anything else alongside a key, which aren't secret
+    let secret =
+        credentials::private_key_block(file_data).unwrap_or_else(|| file_data_string.clone());
+
+    PatternMatch {
+        secret,
+        line: file_data_string.clone(),
+        text: file_data_string,
+        identity: Some(format!("{:x}", Sha256::digest(file_data))),
+        ..new_match(ctx, file_info, MatchType::File, file_name)
+    }
+}
+
+// A match along with the rest of the value it starts, e.g. `Password=hunter2` when a rule only
+// finds `Password=`. Values end at whitespace, quotes or separators.
+fn value_at<'a>(line: &'a str, mat: &regex::Match<'a>) -> &'a str {
+    let rest = &line[mat.end()..];
+    let end = rest
+        .find(|c: char| c.is_whitespace() || "\"'`;,<>&)]}".contains(c))
+        .unwrap_or(rest.len());
+    &line[mat.start()..mat.end() + end]
+}
+
+fn check_patterns<F>(
+    ctx: &ScanContext<F>,
+    line: &str,
+    // the line before this one, which can hold a `sourcesecrets:allow` marker for it
+    previous: Option<&str>,
+    change_type: ChangeType,
+    file_name: &str,
+    location: &str,
+    // line number and column the line's text starts at, if it's known
+    position: Option<(usize, usize)>,
+) where
+    F: Fn(PatternMatch),
+{
+    for pattern in ctx.patterns {
+        if !pattern.enabled.unwrap_or(true) {
+            continue;
         }
+
+        if !pattern.applies_to(file_name) {
+            continue;
+        }
+
+        let regex = match pattern.regex.as_ref() {
+            Some(regex) => regex,
+            None => continue,
+        };
+
+        if let Some(keywords) = pattern.keywords.as_ref() {
+            let lowercase_line = line.to_lowercase();
+            if !keywords
+                .iter()
+                .any(|keyword| lowercase_line.contains(&keyword.to_lowercase()))
+            {
+                continue;
+            }
+        }
+
+        // only look for capture groups when a rule needs them since it's slower
+        let (mat, secret) = match pattern.secret_group {
+            Some(group) => match regex.captures(line) {
+                Some(captures) => (
+                    captures.get(0).unwrap(),
+                    captures.get(group).map_or("", |group| group.as_str()),
+                ),
+                None => continue,
+            },
+            None => match regex.find(line) {
+                Some(mat) => (mat, mat.as_str()),
+                None => continue,
+            },
+        };
+
+        if let Some(min_entropy) = pattern.min_entropy {
+            if entropy(secret) < min_entropy {
+                continue;
+            }
+        }
+
+        if suppress::is_allowed_inline(line, previous, pattern.rule_id()) {
+            continue;
+        }
+
+        // keep everything after the start of the match since patterns like "Password="
+        // only find where the secret starts
+        let matched_text = &line[mat.start()..];
+        let matched = PatternMatch {
+            text: matched_text.trim().to_owned(),
+            secret: secret.to_owned(),
+            line: line.to_owned(),
+            location: location.to_owned(),
+            line_number: position.map(|(number, _)| number),
+            column: position.map(|(_, column)| column + line[..mat.start()].chars().count()),
+            change_type,
+            identity: pattern
+                .secret_group
+                .map_or_else(|| Some(value_at(line, &mat).to_owned()), |_| None),
+            ..new_match(ctx, pattern, MatchType::Pattern, file_name)
+        };
+
+        (ctx.on_found)(matched);
     }
 }
@@ -0,0 +1,312 @@
+use csv;
+use definitions::Rule;
+use html::HtmlOutput;
+use sarif::SarifOutput;
+use serde_json::{self, Value};
+use sha2::{Digest, Sha256};
+use std::io::{self, Write};
+use std::str::FromStr;
+use PatternMatch;
+
+/// Formats matches can be written in
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OutputFormat {
+    Csv,
+    /// A single document with the scan's metadata and every match
+    Json,
+    /// One match per line, written as soon as it's found
+    JsonLines,
+    /// SARIF 2.1.0, for code scanning dashboards
+    Sarif,
+    /// A single page for triaging matches, which doesn't need anything else to view it
+    Html,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(OutputFormat::Csv),
+            "json" => Ok(OutputFormat::Json),
+            "jsonl" => Ok(OutputFormat::JsonLines),
+            "sarif" => Ok(OutputFormat::Sarif),
+            "html" => Ok(OutputFormat::Html),
+            _ => Err(format!(
+                "unknown format {} (expected csv, json, jsonl, sarif or html)",
+                s
+            )),
+        }
+    }
+}
+
+/// Details of a scan, written along with its matches by formats that have room for them
+#[derive(Serialize)]
+pub struct ScanMetadata {
+    pub tool: &'static str,
+    pub version: &'static str,
+    /// Seconds since the Unix epoch
+    pub started_at: u64,
+    pub finished_at: u64,
+    pub repos: Vec<String>,
+    pub commits: usize,
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub rule_packs: Vec<String>,
+    pub definitions: Vec<String>,
+    /// Number of rules used, after `--min-severity` and `--tags`
+    pub rules: usize,
+    /// How secrets were redacted, if they were
+    pub redaction: Option<String>,
+    pub baseline: Option<String>,
+    pub findings: usize,
+    /// Matches left out because they were already in the baseline
+    pub suppressed_by_baseline: usize,
+}
+
+pub trait OutputWriter {
+    fn write_match(&mut self, pattern_match: &PatternMatch) -> io::Result<()>;
+
+    fn flush(&mut self) -> io::Result<()>;
+
+    /// Called once every match has been written
+    fn finish(&mut self, metadata: &ScanMetadata) -> io::Result<()>;
+}
+
+//
+// Create a writer for the given format. `rules` are every rule used in the scan, for formats that
+// describe them along with the matches.
+//
+pub fn new_writer(
+    format: OutputFormat,
+    output: Box<dyn Write>,
+    rules: &[&dyn Rule],
+) -> Box<dyn OutputWriter> {
+    match format {
+        OutputFormat::Csv => Box::new(CsvOutput {
+            writer: csv::Writer::from_writer(output),
+        }),
+        OutputFormat::Json => Box::new(JsonOutput {
+            writer: output,
+            first: true,
+        }),
+        OutputFormat::JsonLines => Box::new(JsonLinesOutput { writer: output }),
+        OutputFormat::Sarif => Box::new(SarifOutput::new(output, rules)),
+        OutputFormat::Html => Box::new(HtmlOutput::new(output)),
+    }
+}
+
+struct CsvOutput {
+    writer: csv::Writer<Box<dyn Write>>,
+}
+
+impl OutputWriter for CsvOutput {
+    fn write_match(&mut self, pattern_match: &PatternMatch) -> io::Result<()> {
+        self.writer
+            .serialize(pattern_match)
+            .map_err(io::Error::from)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    fn finish(&mut self, _metadata: &ScanMetadata) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+struct JsonLinesOutput {
+    writer: Box<dyn Write>,
+}
+
+impl OutputWriter for JsonLinesOutput {
+    fn write_match(&mut self, pattern_match: &PatternMatch) -> io::Result<()> {
+        serde_json::to_writer(&mut self.writer, &to_json(pattern_match))?;
+        self.writer.write_all(b"\n")
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    fn finish(&mut self, _metadata: &ScanMetadata) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+// Matches are still streamed out as they're found, with the metadata written after them once
+// the scan is done
+struct JsonOutput {
+    writer: Box<dyn Write>,
+    first: bool,
+}
+
+impl JsonOutput {
+    fn start(&mut self) -> io::Result<()> {
+        if self.first {
+            self.first = false;
+            self.writer.write_all(b"{\"findings\":[\n")
+        } else {
+            self.writer.write_all(b",\n")
+        }
+    }
+}
+
+impl OutputWriter for JsonOutput {
+    fn write_match(&mut self, pattern_match: &PatternMatch) -> io::Result<()> {
+        self.start()?;
+        serde_json::to_writer(&mut self.writer, &to_json(pattern_match))?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    fn finish(&mut self, metadata: &ScanMetadata) -> io::Result<()> {
+        if self.first {
+            self.writer.write_all(b"{\"findings\":[")?;
+        }
+        self.writer.write_all(b"\n],\"scan\":")?;
+        serde_json::to_writer(&mut self.writer, metadata)?;
+        self.writer.write_all(b"}\n")?;
+        self.writer.flush()
+    }
+}
+
+// Tags and branches are joined into one column for CSV, but JSON can hold them as lists
+fn to_json(pattern_match: &PatternMatch) -> Value {
+    let mut value = serde_json::to_value(pattern_match).expect("failed to serialize pattern");
+    value["tags"] = Value::from(pattern_match.tags.clone());
+    value["branches"] = Value::from(pattern_match.branches.clone());
+    value
+}
+
+//
+// Hash identifying a secret found by a rule, which stays the same wherever and whenever it's
+// found. File rules hash the contents of the file they matched, since their secret only
+// describes it and is the same for e.g. every key store with a password, and rules without a
+// secret group hash the value after their match, since `Password=` alone says nothing.
+//
+pub fn fingerprint(pattern_match: &PatternMatch) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(pattern_match.rule_id.as_bytes());
+    hasher.update(b"\0");
+    match pattern_match.identity.as_ref() {
+        Some(identity) => hasher.update(identity.as_bytes()),
+        None => hasher.update(pattern_match.secret.as_bytes()),
+    }
+
+    format!("{:x}", hasher.finalize())
+}
+
+//
+// Seconds since the Unix epoch for an ISO 8601 time with an offset, like the commit dates git
+// gives (`2018-01-01T12:00:00+01:00`)
+//
+pub fn parse_timestamp(date: &str) -> Option<i64> {
+    let field = |range: ::std::ops::Range<usize>| -> Option<i64> { date.get(range)?.parse().ok() };
+    let (year, month, day) = (field(0..4)?, field(5..7)?, field(8..10)?);
+    let (hour, minute, second) = (field(11..13)?, field(14..16)?, field(17..19)?);
+    let offset = match date.get(19..) {
+        Some("Z") | Some("") => 0,
+        Some(offset) if offset.len() == 6 => {
+            let seconds = field(20..22)? * 3600 + field(23..25)? * 60;
+            if offset.starts_with('-') {
+                -seconds
+            } else {
+                seconds
+            }
+        }
+        _ => return None,
+    };
+
+    // days since 1970-01-01 from a civil date, the inverse of what `timestamp` does
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_index = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    Some(days * 86400 + hour * 3600 + minute * 60 + second - offset)
+}
+
+// ISO 8601 UTC time for seconds since the Unix epoch
+pub fn timestamp(seconds: u64) -> String {
+    let days = (seconds / 86400) as i64;
+    let time = seconds % 86400;
+
+    // civil date from days since 1970-01-01, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use definitions::{MatchType, Severity};
+    use git::ChangeType;
+
+    fn file_match(file: &str, contents: &[u8]) -> PatternMatch {
+        PatternMatch {
+            description: "Private key file".to_owned(),
+            text: "format=PKCS#12; encrypted=true".to_owned(),
+            repo_path: "repo".to_owned(),
+            file: file.to_owned(),
+            full_path: format!("repo/{}", file),
+            match_type: MatchType::File,
+            change_type: ChangeType::Unknown,
+            commit_hash: "0123abcd".to_owned(),
+            parent_hash: None,
+            commit_date: "2020-01-01T00:00:00+00:00".to_owned(),
+            location: String::new(),
+            rule_id: "pfx-file".to_owned(),
+            secret: "format=PKCS#12; encrypted=true".to_owned(),
+            commit_author: String::new(),
+            commit_message: String::new(),
+            severity: Severity::High,
+            tags: Vec::new(),
+            remediation: String::new(),
+            line_number: None,
+            column: None,
+            fingerprint: String::new(),
+            present_at_head: None,
+            branches: Vec::new(),
+            line: String::new(),
+            identity: Some(format!("{:x}", Sha256::digest(contents))),
+        }
+    }
+
+    #[test]
+    fn file_rules_fingerprint_the_file() {
+        let first = file_match("a.pfx", b"first store");
+        let second = file_match("b.pfx", b"second store");
+        let moved = file_match("moved/a.pfx", b"first store");
+
+        // both files are described the same way, but hold different keys
+        assert_eq!(first.secret, second.secret);
+        assert_ne!(fingerprint(&first), fingerprint(&second));
+        assert_eq!(fingerprint(&first), fingerprint(&moved));
+    }
+}
@@ -0,0 +1,22 @@
+/// Rule packs compiled into the binary, by name
+pub const PACKS: &[(&str, &str)] = &[
+    ("generic", include_str!("../rules/generic.toml")),
+    ("cloud", include_str!("../rules/cloud.toml")),
+    ("vcs", include_str!("../rules/vcs.toml")),
+    ("keys", include_str!("../rules/keys.toml")),
+    ("database", include_str!("../rules/database.toml")),
+    ("dotnet", include_str!("../rules/dotnet.toml")),
+    ("java", include_str!("../rules/java.toml")),
+    ("node", include_str!("../rules/node.toml")),
+];
+
+pub fn get(name: &str) -> Option<&'static str> {
+    PACKS
+        .iter()
+        .find(|(pack, _)| *pack == name)
+        .map(|(_, contents)| *contents)
+}
+
+pub fn names() -> Vec<&'static str> {
+    PACKS.iter().map(|(name, _)| *name).collect()
+}
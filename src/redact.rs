@@ -0,0 +1,192 @@
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::str::FromStr;
+use PatternMatch;
+
+/// Characters kept at each end of a secret by `partial` when a count isn't given
+const DEFAULT_PARTIAL_LENGTH: usize = 4;
+const MASK: &str = "[REDACTED]";
+
+#[derive(Clone, Debug)]
+enum Mode {
+    /// Replace secrets entirely
+    Full,
+    /// Keep this many characters at the start and end of each secret
+    Partial(usize),
+    /// Replace secrets with a hash of them and the salt, so the same secret can still be
+    /// spotted in different places
+    Hash,
+}
+
+//
+// How secrets are hidden in the results. The salt keys hashes of secrets and their fingerprints,
+// so they can't be checked against guesses without it. Fingerprints have to stay the same between
+// scans to be of any use, so they're only keyed with a salt that was given, and otherwise with a
+// key for the repo they were found in.
+//
+#[derive(Clone, Debug)]
+pub struct Redaction {
+    mode: Mode,
+    salt: String,
+    // the salt, when it's known rather than random
+    key: Option<String>,
+}
+
+impl FromStr for Redaction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, ':');
+        let mode = match (parts.next().unwrap(), parts.next()) {
+            ("full", None) => Mode::Full,
+            ("partial", None) => Mode::Partial(DEFAULT_PARTIAL_LENGTH),
+            ("partial", Some(length)) => length
+                .parse()
+                .map(Mode::Partial)
+                .map_err(|_| format!("invalid partial redaction length {}", length))?,
+            ("hash", None) => Mode::Hash,
+            _ => {
+                return Err(format!(
+                    "unknown redaction {} (expected full, partial, partial:N or hash)",
+                    s
+                ))
+            }
+        };
+
+        Ok(Redaction {
+            mode,
+            salt: random_salt(),
+            key: None,
+        })
+    }
+}
+
+impl fmt::Display for Redaction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // the salt is left out since it's what keeps the hashes from being guessed
+        match self.mode {
+            Mode::Full => write!(f, "full"),
+            Mode::Partial(length) => write!(f, "partial:{}", length),
+            Mode::Hash => write!(f, "hash"),
+        }
+    }
+}
+
+impl Redaction {
+    /// Use a known salt instead of a random one, so hashes and fingerprints can be compared
+    /// between scans
+    pub fn with_salt(self, salt: &str) -> Redaction {
+        Redaction {
+            salt: salt.to_owned(),
+            key: Some(salt.to_owned()),
+            ..self
+        }
+    }
+
+    pub fn redact(&self, secret: &str) -> String {
+        if secret.is_empty() {
+            return String::new();
+        }
+
+        match self.mode {
+            Mode::Full => MASK.to_owned(),
+            Mode::Partial(length) => {
+                let chars: Vec<char> = secret.chars().collect();
+                // short secrets would be mostly given away by their ends
+                if length == 0 || chars.len() < length * 4 {
+                    return MASK.to_owned();
+                }
+
+                let start: String = chars[..length].iter().collect();
+                let end: String = chars[chars.len() - length..].iter().collect();
+                format!("{}...{}", start, end)
+            }
+            Mode::Hash => {
+                let mut hasher = Sha256::new();
+                hasher.update(self.salt.as_bytes());
+                hasher.update(secret.as_bytes());
+                let hash = format!("{:x}", hasher.finalize());
+                format!("hash:{}", &hash[..16])
+            }
+        }
+    }
+
+    //
+    // Key a fingerprint with the salt, or the key of the repo it was found in when the salt is
+    // random. A plain hash of the rule and secret could be checked against guesses of a weak
+    // secret, which would give away what redaction hides.
+    //
+    pub fn fingerprint(&self, fingerprint: &str, repo_key: &str) -> String {
+        let key = self.key.as_ref().map_or(repo_key, String::as_str);
+        let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes())
+            .expect("HMAC takes keys of any length");
+        mac.update(fingerprint.as_bytes());
+        format!("{:x}", mac.finalize().into_bytes())
+    }
+
+    //
+    // Hide the secret in a match along with the text around it, which often holds the rest of
+    // the secret for rules that only find where one starts
+    //
+    pub fn apply(&self, pattern_match: &mut PatternMatch) {
+        pattern_match.secret = self.redact(&pattern_match.secret);
+        pattern_match.text = self.redact(&pattern_match.text);
+        pattern_match.line = self.redact(&pattern_match.line);
+    }
+}
+
+//
+// Key for a repo's fingerprints when no salt is given, from the trees history starts at. It's the
+// same every scan, but isn't in the results so it can't be used to check guesses against them.
+//
+pub fn repo_key(root_trees: &[String]) -> String {
+    format!("{:x}", Sha256::digest(root_trees.join("\n").as_bytes()))
+}
+
+fn random_salt() -> String {
+    // the standard library seeds each RandomState from the OS' random number generator
+    let hasher = RandomState::new().build_hasher();
+    format!("{:016x}", hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FINGERPRINT: &str = "5e884898da28047151d0e56f8dc6292773603d0d6aabbdd62a11ef721d1542d8";
+    const REPO_KEY: &str = "repo";
+
+    #[test]
+    fn fingerprints_are_keyed_with_the_salt() {
+        let redaction: Redaction = "full".parse().unwrap();
+        let first = redaction.clone().with_salt("first");
+        let second = redaction.with_salt("second");
+
+        let fingerprint = first.fingerprint(FINGERPRINT, REPO_KEY);
+        assert_ne!(fingerprint, FINGERPRINT);
+        assert_eq!(fingerprint.len(), FINGERPRINT.len());
+        assert_eq!(fingerprint, first.fingerprint(FINGERPRINT, "other repo"));
+        assert_ne!(fingerprint, second.fingerprint(FINGERPRINT, REPO_KEY));
+    }
+
+    #[test]
+    fn random_salts_only_change_hashes() {
+        let first: Redaction = "hash".parse().unwrap();
+        let second: Redaction = "hash".parse().unwrap();
+        assert_ne!(first.redact("hunter2"), second.redact("hunter2"));
+
+        // fingerprints are keyed by the repo instead, so they can be compared between scans
+        assert_eq!(
+            first.fingerprint(FINGERPRINT, REPO_KEY),
+            second.fingerprint(FINGERPRINT, REPO_KEY)
+        );
+        assert_ne!(
+            first.fingerprint(FINGERPRINT, REPO_KEY),
+            first.fingerprint(FINGERPRINT, "other repo")
+        );
+        assert_ne!(first.fingerprint(FINGERPRINT, REPO_KEY), FINGERPRINT);
+    }
+}
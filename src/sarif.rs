@@ -0,0 +1,345 @@
+use definitions::{Rule, Severity};
+use git::ChangeType;
+use output::{timestamp, OutputWriter, ScanMetadata};
+use serde_json::{self, Map, Value};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::Path;
+use PatternMatch;
+
+const SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+const INFORMATION_URI: &str = "https://github.com/landaire/sourcesecrets";
+
+// What SARIF needs to know about each rule, copied out of the definitions
+struct SarifRule {
+    id: String,
+    description: String,
+    severity: Severity,
+    tags: Vec<String>,
+    remediation: Option<String>,
+}
+
+//
+// Writes a SARIF 2.1.0 log with a single run. Results are streamed out as they're found, and the
+// rules and the commits they were found in are written once the scan is done.
+//
+pub struct SarifOutput {
+    writer: Box<dyn Write>,
+    rules: Vec<SarifRule>,
+    rule_indexes: HashMap<String, usize>,
+    // repo path and commit hash of each revision results were found in, the position of each
+    // is used to name the uriBaseId their paths are relative to
+    revisions: Vec<(String, String)>,
+    first: bool,
+}
+
+impl SarifOutput {
+    pub fn new(writer: Box<dyn Write>, rules: &[&dyn Rule]) -> SarifOutput {
+        let rules: Vec<SarifRule> = rules
+            .iter()
+            .map(|rule| SarifRule {
+                id: rule.rule_id().to_owned(),
+                description: rule.description().to_owned(),
+                severity: rule.severity(),
+                tags: rule.tags().to_vec(),
+                remediation: rule.remediation().map(str::to_owned),
+            })
+            .collect();
+        let rule_indexes = rules
+            .iter()
+            .enumerate()
+            .map(|(i, rule)| (rule.id.clone(), i))
+            .collect();
+
+        SarifOutput {
+            writer,
+            rules,
+            rule_indexes,
+            revisions: Vec::new(),
+            first: true,
+        }
+    }
+
+    fn start(&mut self) -> io::Result<()> {
+        if !self.first {
+            return self.writer.write_all(b",\n");
+        }
+
+        self.first = false;
+        writeln!(
+            self.writer,
+            "{{\"$schema\":\"{}\",\"version\":\"2.1.0\",\"runs\":[{{\"columnKind\":\"unicodeCodePoints\",\"results\":[",
+            SCHEMA
+        )
+    }
+
+    fn uri_base_id(&mut self, pattern_match: &PatternMatch) -> String {
+        // removed lines are numbered as they were before the commit, and are only in its parent
+        let commit = match pattern_match.parent_hash.as_ref() {
+            Some(parent) if pattern_match.change_type == ChangeType::Removal => parent.clone(),
+            _ => pattern_match.commit_hash.clone(),
+        };
+        let revision = (pattern_match.repo_path.clone(), commit);
+        let index = match self.revisions.iter().position(|r| *r == revision) {
+            Some(index) => index,
+            None => {
+                self.revisions.push(revision);
+                self.revisions.len() - 1
+            }
+        };
+
+        format!("REVISION{}", index)
+    }
+
+    fn result(&mut self, pattern_match: &PatternMatch) -> Value {
+        let mut region = Map::new();
+        if let Some(line_number) = pattern_match.line_number {
+            region.insert("startLine".to_owned(), Value::from(line_number));
+            if let Some(column) = pattern_match.column {
+                region.insert("startColumn".to_owned(), Value::from(column));
+            }
+            region.insert("snippet".to_owned(), json!({ "text": pattern_match.text }));
+        }
+
+        let mut physical_location = json!({
+            "artifactLocation": {
+                "uri": encode_uri_path(&pattern_match.file),
+                "uriBaseId": self.uri_base_id(pattern_match),
+            },
+        });
+        if !region.is_empty() {
+            physical_location["region"] = Value::Object(region);
+        }
+
+        let mut properties = json!({
+            "commit": pattern_match.commit_hash,
+            "commitDate": pattern_match.commit_date,
+            "commitAuthor": pattern_match.commit_author,
+            "commitMessage": pattern_match.commit_message,
+            "changeType": pattern_match.change_type,
+            "matchType": pattern_match.match_type,
+            "tags": pattern_match.tags,
+        });
+        if let Some(present_at_head) = pattern_match.present_at_head {
+            properties["presentAtHead"] = Value::from(present_at_head);
+            properties["branches"] = Value::from(pattern_match.branches.clone());
+        }
+        if !pattern_match.location.is_empty() {
+            properties["location"] = Value::from(pattern_match.location.clone());
+        }
+
+        let mut result = json!({
+            "ruleId": pattern_match.rule_id,
+            "level": level(pattern_match.severity),
+            "message": { "text": pattern_match.description },
+            "locations": [{ "physicalLocation": physical_location }],
+            "partialFingerprints": { "secretHash/v1": pattern_match.fingerprint },
+            "properties": properties,
+        });
+        if let Some(index) = self.rule_indexes.get(&pattern_match.rule_id) {
+            result["ruleIndex"] = Value::from(*index);
+        }
+
+        result
+    }
+
+    fn rule_descriptors(&self) -> Vec<Value> {
+        self.rules
+            .iter()
+            .map(|rule| {
+                let mut properties = json!({ "tags": rule.tags });
+                if let Some(score) = security_severity(rule.severity) {
+                    properties["security-severity"] = Value::from(score);
+                }
+
+                let mut descriptor = json!({
+                    "id": rule.id,
+                    "shortDescription": { "text": rule.description },
+                    "defaultConfiguration": { "level": level(rule.severity) },
+                    "properties": properties,
+                });
+                if let Some(remediation) = rule.remediation.as_ref() {
+                    descriptor["help"] = json!({ "text": remediation });
+                }
+
+                descriptor
+            })
+            .collect()
+    }
+}
+
+impl OutputWriter for SarifOutput {
+    fn write_match(&mut self, pattern_match: &PatternMatch) -> io::Result<()> {
+        self.start()?;
+        let result = self.result(pattern_match);
+        serde_json::to_writer(&mut self.writer, &result)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    fn finish(&mut self, metadata: &ScanMetadata) -> io::Result<()> {
+        if self.first {
+            self.start()?;
+        }
+
+        // each commit results were found in gets a base the results' paths are relative to, so
+        // the commit can be found from a result's location
+        let mut base_ids = Map::new();
+        let mut provenance = Vec::new();
+        for (i, (repo_path, commit_hash)) in self.revisions.iter().enumerate() {
+            let uri_base_id = format!("REVISION{}", i);
+            let repo_uri = repo_uri(repo_path);
+            // every revision of a repo has the same working directory, so say which one it is
+            base_ids.insert(
+                uri_base_id.clone(),
+                json!({
+                    "uri": repo_uri,
+                    "description": { "text": format!("Revision {}", commit_hash) },
+                }),
+            );
+            provenance.push(json!({
+                "repositoryUri": repo_uri,
+                "revisionId": commit_hash,
+                "mappedTo": { "uriBaseId": uri_base_id },
+            }));
+        }
+
+        let run = json!({
+            "tool": {
+                "driver": {
+                    "name": metadata.tool,
+                    "version": metadata.version,
+                    "informationUri": INFORMATION_URI,
+                    "rules": self.rule_descriptors(),
+                },
+            },
+            "invocations": [{
+                "executionSuccessful": true,
+                "startTimeUtc": timestamp(metadata.started_at),
+                "endTimeUtc": timestamp(metadata.finished_at),
+            }],
+            "originalUriBaseIds": base_ids,
+            "versionControlProvenance": provenance,
+        });
+
+        // the run's other properties follow its results, which have already been written
+        let run = serde_json::to_string(&run)?;
+        writeln!(self.writer, "\n],{}]}}", &run[1..])?;
+        self.writer.flush()
+    }
+}
+
+fn level(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Critical | Severity::High => "error",
+        Severity::Medium => "warning",
+        Severity::Low | Severity::Info => "note",
+    }
+}
+
+// Score code scanning dashboards use to rank security results, on CVSS' 0-10 scale
+fn security_severity(severity: Severity) -> Option<&'static str> {
+    match severity {
+        Severity::Critical => Some("9.5"),
+        Severity::High => Some("8.0"),
+        Severity::Medium => Some("5.5"),
+        Severity::Low => Some("3.0"),
+        Severity::Info => None,
+    }
+}
+
+// Absolute `file://` URI of a repo's working directory, ending in `/` so paths resolve under it
+fn repo_uri(repo_path: &str) -> String {
+    let path = Path::new(repo_path)
+        .canonicalize()
+        .map(|path| path.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| repo_path.to_owned());
+    // Windows paths come back as `\\?\C:\...`
+    let path = path.trim_start_matches(r"\\?\").replace('\\', "/");
+    let path = path.trim_end_matches('/');
+
+    if path.starts_with('/') {
+        format!("file://{}/", encode_uri_path(path))
+    } else {
+        format!("file:///{}/", encode_uri_path(path))
+    }
+}
+
+// Percent-encode everything in a path that isn't allowed in a URI path as is
+fn encode_uri_path(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' => encoded.push(byte as char),
+            b'-' | b'.' | b'_' | b'~' | b'/' | b'!' | b'$' | b'&' | b'\'' | b'(' | b')' | b'*'
+            | b'+' | b',' | b';' | b'=' | b':' | b'@' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use definitions::MatchType;
+
+    const COMMIT: &str = "1111111111111111111111111111111111111111";
+    const PARENT: &str = "2222222222222222222222222222222222222222";
+
+    fn pattern_match(change_type: ChangeType) -> PatternMatch {
+        PatternMatch {
+            description: "Password properties".to_owned(),
+            text: "Password=hunter2".to_owned(),
+            repo_path: "repo".to_owned(),
+            file: "app.config".to_owned(),
+            full_path: "repo/app.config".to_owned(),
+            match_type: MatchType::Pattern,
+            change_type,
+            commit_hash: COMMIT.to_owned(),
+            commit_date: "2020-01-01T00:00:00+00:00".to_owned(),
+            location: String::new(),
+            rule_id: "password-property".to_owned(),
+            secret: "Password=".to_owned(),
+            commit_author: String::new(),
+            commit_message: String::new(),
+            severity: Severity::Medium,
+            tags: Vec::new(),
+            remediation: String::new(),
+            line_number: Some(3),
+            column: Some(1),
+            fingerprint: String::new(),
+            present_at_head: None,
+            branches: Vec::new(),
+            line: "Password=hunter2".to_owned(),
+            parent_hash: Some(PARENT.to_owned()),
+            identity: None,
+        }
+    }
+
+    #[test]
+    fn removed_secrets_are_found_in_the_parent() {
+        let mut output = SarifOutput::new(Box::new(io::sink()), &[]);
+        let removed = output.result(&pattern_match(ChangeType::Removal));
+        let added = output.result(&pattern_match(ChangeType::Addition));
+
+        let base_id = |result: &Value| {
+            result["locations"][0]["physicalLocation"]["artifactLocation"]["uriBaseId"].clone()
+        };
+        assert_eq!(base_id(&removed), "REVISION0");
+        assert_eq!(base_id(&added), "REVISION1");
+        assert_eq!(
+            output.revisions,
+            vec![
+                ("repo".to_owned(), PARENT.to_owned()),
+                ("repo".to_owned(), COMMIT.to_owned()),
+            ]
+        );
+        // the result itself still names the commit that removed it
+        assert_eq!(removed["properties"]["commit"], COMMIT);
+    }
+}
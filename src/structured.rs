@@ -0,0 +1,486 @@
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use serde::Deserialize;
+use serde_json;
+use serde_yaml;
+use toml;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Json,
+    Yaml,
+    Toml,
+    Ini,
+    Properties,
+    Xml,
+}
+
+/// A single key/value pair pulled out of a structured file
+pub struct Entry {
+    /// Name of the key or attribute the value belongs to
+    pub key: String,
+    /// Full path to the value, e.g. `ConnectionStrings.Default` or `configuration/appSettings/add[@key='DbPassword']`
+    pub path: String,
+    pub value: String,
+}
+
+// Attributes that name the setting in elements like <add key="DbPassword" value="..." />
+const XML_NAME_ATTRIBUTES: &[&str] = &["key", "name"];
+const XML_VALUE_ATTRIBUTES: &[&str] = &["value", "connectionString"];
+
+pub fn format_for(file_name: &str) -> Option<Format> {
+    let file_name = file_name.to_lowercase();
+    let base_name = file_name.rsplit('/').next().unwrap();
+    let extension = match base_name.rfind('.') {
+        Some(i) => &base_name[i + 1..],
+        None => return None,
+    };
+
+    match extension {
+        "json" => Some(Format::Json),
+        "yml" | "yaml" => Some(Format::Yaml),
+        "toml" => Some(Format::Toml),
+        "ini" => Some(Format::Ini),
+        "properties" | "env" => Some(Format::Properties),
+        "xml" | "config" | "resx" | "settings" => Some(Format::Xml),
+        _ => None,
+    }
+}
+
+//
+// Parse a structured file and flatten it into a list of key/value pairs
+//
+pub fn parse(format: Format, text: &str) -> Result<Vec<Entry>, String> {
+    let mut entries = Vec::new();
+
+    match format {
+        Format::Json => {
+            let value: serde_json::Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+            flatten_json("", "", &value, &mut entries);
+        }
+        Format::Yaml => {
+            for document in serde_yaml::Deserializer::from_str(text) {
+                let value = serde_yaml::Value::deserialize(document).map_err(|e| e.to_string())?;
+                flatten_yaml("", "", &value, &mut entries);
+            }
+        }
+        Format::Toml => {
+            let value: toml::Value = toml::from_str(text).map_err(|e| e.to_string())?;
+            flatten_toml("", "", &value, &mut entries);
+        }
+        Format::Ini => parse_ini(text, &mut entries),
+        Format::Properties => parse_properties(text, &mut entries),
+        Format::Xml => parse_xml(text, &mut entries)?,
+    }
+
+    Ok(entries)
+}
+
+//
+// The line an entry's value is on and the line before it, for `sourcesecrets:allow` markers.
+// Most parsers don't say where a value came from, so this is the first line with both the key
+// and the value, or failing that the first with the value.
+//
+pub fn find_line<'a>(text: &'a str, entry: &Entry) -> Option<(&'a str, Option<&'a str>)> {
+    let value = entry.value.trim();
+    let lines: Vec<&str> = text.lines().collect();
+    let index = lines
+        .iter()
+        .position(|line| line.contains(value) && line.contains(entry.key.as_str()))
+        .or_else(|| lines.iter().position(|line| line.contains(value)))?;
+
+    Some((lines[index].trim(), index.checked_sub(1).map(|i| lines[i].trim())))
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+fn flatten_json(path: &str, key: &str, value: &serde_json::Value, entries: &mut Vec<Entry>) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (k, v) in map {
+                flatten_json(&join(path, k), k, v, entries);
+            }
+        }
+        serde_json::Value::Array(values) => {
+            for (i, v) in values.iter().enumerate() {
+                flatten_json(&format!("{}[{}]", path, i), key, v, entries);
+            }
+        }
+        serde_json::Value::String(s) => push(entries, key, path, s.clone()),
+        serde_json::Value::Number(n) => push(entries, key, path, n.to_string()),
+        _ => {}
+    }
+}
+
+fn flatten_yaml(path: &str, key: &str, value: &serde_yaml::Value, entries: &mut Vec<Entry>) {
+    match value {
+        serde_yaml::Value::Mapping(map) => {
+            for (k, v) in map {
+                let k = match yaml_scalar(k) {
+                    Some(k) => k,
+                    None => continue,
+                };
+                flatten_yaml(&join(path, &k), &k, v, entries);
+            }
+        }
+        serde_yaml::Value::Sequence(values) => {
+            for (i, v) in values.iter().enumerate() {
+                flatten_yaml(&format!("{}[{}]", path, i), key, v, entries);
+            }
+        }
+        serde_yaml::Value::Tagged(tagged) => flatten_yaml(path, key, &tagged.value, entries),
+        scalar => {
+            if let Some(s) = yaml_scalar(scalar) {
+                push(entries, key, path, s);
+            }
+        }
+    }
+}
+
+fn yaml_scalar(value: &serde_yaml::Value) -> Option<String> {
+    match value {
+        serde_yaml::Value::String(s) => Some(s.clone()),
+        serde_yaml::Value::Number(n) => Some(n.to_string()),
+        serde_yaml::Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+fn flatten_toml(path: &str, key: &str, value: &toml::Value, entries: &mut Vec<Entry>) {
+    match value {
+        toml::Value::Table(table) => {
+            for (k, v) in table {
+                flatten_toml(&join(path, k), k, v, entries);
+            }
+        }
+        toml::Value::Array(values) => {
+            for (i, v) in values.iter().enumerate() {
+                flatten_toml(&format!("{}[{}]", path, i), key, v, entries);
+            }
+        }
+        toml::Value::String(s) => push(entries, key, path, s.clone()),
+        toml::Value::Integer(i) => push(entries, key, path, i.to_string()),
+        _ => {}
+    }
+}
+
+fn parse_ini(text: &str, entries: &mut Vec<Entry>) {
+    let mut section = String::new();
+
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+            continue;
+        }
+
+        if line.starts_with('[') && line.ends_with(']') {
+            section = line[1..line.len() - 1].trim().to_string();
+            continue;
+        }
+
+        if let Some((key, value)) = split_pair(line, &['=', ':']) {
+            push(entries, key, &join(&section, key), unquote(value));
+        }
+    }
+}
+
+fn parse_properties(text: &str, entries: &mut Vec<Entry>) {
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with('!') {
+            continue;
+        }
+
+        // .env files may prefix assignments with `export`
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        if let Some((key, value)) = split_pair(line, &['=', ':']) {
+            push(entries, key, key, unquote(value));
+        }
+    }
+}
+
+fn split_pair<'a>(line: &'a str, separators: &[char]) -> Option<(&'a str, &'a str)> {
+    let i = line.find(|c| separators.contains(&c))?;
+    let key = line[..i].trim();
+    if key.is_empty() {
+        return None;
+    }
+
+    Some((key, line[i + 1..].trim()))
+}
+
+fn unquote(value: &str) -> String {
+    let quoted = value.len() >= 2
+        && ((value.starts_with('"') && value.ends_with('"'))
+            || (value.starts_with('\'') && value.ends_with('\'')));
+
+    if quoted {
+        value[1..value.len() - 1].to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+fn parse_xml(text: &str, entries: &mut Vec<Entry>) -> Result<(), String> {
+    let mut reader = Reader::from_str(text);
+    reader.config_mut().trim_text(true);
+
+    let mut elements: Vec<String> = Vec::new();
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) => {
+                let path = xml_element_path(&elements, &e);
+                xml_attributes(&path, &e, entries);
+                elements.push(path);
+            }
+            Ok(Event::Empty(e)) => {
+                let path = xml_element_path(&elements, &e);
+                xml_attributes(&path, &e, entries);
+            }
+            Ok(Event::End(_)) => {
+                elements.pop();
+            }
+            Ok(Event::Text(t)) => {
+                if let (Some(path), Ok(value)) = (elements.last(), t.unescape()) {
+                    let key = xml_key(path);
+                    push(entries, &key, path, value.into_owned());
+                }
+            }
+            Ok(Event::CData(t)) => {
+                if let Some(path) = elements.last() {
+                    let key = xml_key(path);
+                    let value = String::from_utf8_lossy(&t).into_owned();
+                    push(entries, &key, path, value);
+                }
+            }
+            Ok(Event::Eof) => break,
+            Ok(_) => {}
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+
+    Ok(())
+}
+
+fn xml_attribute_values(e: &BytesStart) -> Vec<(String, String)> {
+    e.attributes()
+        .filter_map(|a| a.ok())
+        .filter_map(|a| {
+            let key = String::from_utf8_lossy(a.key.as_ref()).into_owned();
+            a.unescape_value().ok().map(|v| (key, v.into_owned()))
+        }).collect()
+}
+
+// Builds the path for an element, naming it after its key/name attribute if it has one
+fn xml_element_path(elements: &[String], e: &BytesStart) -> String {
+    let name = String::from_utf8_lossy(e.name().as_ref()).into_owned();
+    let attributes = xml_attribute_values(e);
+    let segment = match attributes
+        .iter()
+        .find(|(k, _)| XML_NAME_ATTRIBUTES.contains(&k.as_str()))
+    {
+        Some((k, v)) => format!("{}[@{}='{}']", name, k, v),
+        None => name,
+    };
+
+    match elements.last() {
+        Some(parent) => format!("{}/{}", parent, segment),
+        None => segment,
+    }
+}
+
+fn xml_attributes(path: &str, e: &BytesStart, entries: &mut Vec<Entry>) {
+    let attributes = xml_attribute_values(e);
+    let setting_name = attributes
+        .iter()
+        .find(|(k, _)| XML_NAME_ATTRIBUTES.contains(&k.as_str()))
+        .map(|(_, v)| v.clone());
+
+    for (k, v) in &attributes {
+        if XML_NAME_ATTRIBUTES.contains(&k.as_str()) {
+            continue;
+        }
+
+        // <add key="DbPassword" value="..." /> is really the setting DbPassword
+        let key = match setting_name.as_ref() {
+            Some(name) if XML_VALUE_ATTRIBUTES.contains(&k.as_str()) => name.clone(),
+            _ => k.clone(),
+        };
+        push(entries, &key, &format!("{}/@{}", path, k), v.clone());
+    }
+}
+
+// Name of the setting an element path refers to
+fn xml_key(path: &str) -> String {
+    let segment = path.rsplit('/').next().unwrap();
+    match segment.find("='") {
+        Some(i) => segment[i + 2..segment.len() - 2].to_string(),
+        None => segment.to_string(),
+    }
+}
+
+fn push(entries: &mut Vec<Entry>, key: &str, path: &str, value: String) {
+    entries.push(Entry {
+        key: key.to_string(),
+        path: path.to_string(),
+        value,
+    });
+}
+
+// Values that are common stand-ins for a real secret
+const PLACEHOLDER_VALUES: &[&str] = &[
+    "changeme", "change_me", "changeit", "password", "secret", "null", "none", "nil", "todo",
+    "example", "placeholder", "dummy", "redacted", "true", "false", "undefined",
+];
+
+//
+// Determine if a value is a placeholder like `${DB_PASSWORD}`, `<password>` or `changeme`
+// rather than a real secret
+//
+pub fn is_placeholder(value: &str) -> bool {
+    let value = value.trim();
+    let lower = value.to_lowercase();
+
+    let wrapped = |start: &str, end: &str| {
+        value.len() > start.len() + end.len() && value.starts_with(start) && value.ends_with(end)
+    };
+
+    value.is_empty()
+        || wrapped("${", "}")
+        || wrapped("$(", ")")
+        || wrapped("#{", "}")
+        || wrapped("{{", "}}")
+        || wrapped("%", "%")
+        || wrapped("<", ">")
+        || wrapped("__", "__")
+        || (value.starts_with('$') && value[1..].chars().all(|c| c == '_' || c.is_alphanumeric()))
+        || PLACEHOLDER_VALUES.contains(&lower.as_str())
+        || lower.starts_with("your")
+        // values like "xxxxxx" or "******"
+        || value.chars().all(|c| c == value.chars().next().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_line_of_a_yaml_entry() {
+        // the value is on an earlier line too, under another key
+        let text = "user: admin\n  # sourcesecrets:allow\npassword: admin\n";
+        let entries = parse(Format::Yaml, text).unwrap();
+        let password = entries.iter().find(|e| e.key == "password").unwrap();
+
+        assert_eq!(
+            find_line(text, password),
+            Some(("password: admin", Some("# sourcesecrets:allow")))
+        );
+    }
+
+    #[test]
+    fn finds_the_line_of_an_xml_attribute() {
+        let text = "<appSettings>\n  <!-- sourcesecrets:allow -->\n  <add key=\"DbPassword\" value=\"hunter2\" />\n</appSettings>";
+        let entries = parse(Format::Xml, text).unwrap();
+        let password = entries.iter().find(|e| e.value == "hunter2").unwrap();
+
+        let (line, previous) = find_line(text, password).unwrap();
+        assert_eq!(line, "<add key=\"DbPassword\" value=\"hunter2\" />");
+        assert_eq!(previous, Some("<!-- sourcesecrets:allow -->"));
+    }
+
+    fn pairs(entries: &[Entry]) -> Vec<(&str, &str, &str)> {
+        entries
+            .iter()
+            .map(|e| (e.key.as_str(), e.path.as_str(), e.value.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn flattens_json() {
+        let text = r#"{"ConnectionStrings": {"Default": "Server=db;Password=hunter2"}, "Hosts": [{"Token": "abc"}, "b"], "Port": 5432, "Debug": true}"#;
+        let entries = parse(Format::Json, text).unwrap();
+
+        assert_eq!(
+            pairs(&entries),
+            vec![
+                ("Default", "ConnectionStrings.Default", "Server=db;Password=hunter2"),
+                ("Token", "Hosts[0].Token", "abc"),
+                // array items are named after the key holding the array
+                ("Hosts", "Hosts[1]", "b"),
+                ("Port", "Port", "5432"),
+            ]
+        );
+    }
+
+    #[test]
+    fn flattens_every_yaml_document() {
+        let text = "database:\n  password: hunter2\n  hosts:\n    - one\n---\napi_key: abc123\nenabled: true\n";
+        let entries = parse(Format::Yaml, text).unwrap();
+
+        assert_eq!(
+            pairs(&entries),
+            vec![
+                ("password", "database.password", "hunter2"),
+                ("hosts", "database.hosts[0]", "one"),
+                ("api_key", "api_key", "abc123"),
+                ("enabled", "enabled", "true"),
+            ]
+        );
+    }
+
+    #[test]
+    fn flattens_toml() {
+        let text = "[database]\npassword = \"hunter2\"\nport = 5432\n\n[[servers]]\ntoken = \"abc\"\n";
+        let entries = parse(Format::Toml, text).unwrap();
+
+        assert_eq!(
+            pairs(&entries),
+            vec![
+                ("password", "database.password", "hunter2"),
+                ("port", "database.port", "5432"),
+                ("token", "servers[0].token", "abc"),
+            ]
+        );
+    }
+
+    #[test]
+    fn names_xml_settings_by_their_key() {
+        let text = "<configuration><appSettings><add key=\"DbPassword\" value=\"hunter2\" /></appSettings><password>abc</password></configuration>";
+        let entries = parse(Format::Xml, text).unwrap();
+
+        assert_eq!(
+            pairs(&entries),
+            vec![
+                ("DbPassword", "configuration/appSettings/add[@key='DbPassword']/@value", "hunter2"),
+                ("password", "configuration/password", "abc"),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_files_are_errors() {
+        assert!(parse(Format::Json, "{\"password\": ").is_err());
+        assert!(parse(Format::Toml, "password = ").is_err());
+    }
+
+    #[test]
+    fn placeholders() {
+        for value in &[
+            "", "${DB_PASSWORD}", "$(SECRET)", "#{token}", "{{ password }}", "%PASSWORD%",
+            "<password>", "__TOKEN__", "$DB_PASSWORD", "changeme", "Password", "your-api-key",
+            "xxxxxxxx", "********",
+        ] {
+            assert!(is_placeholder(value), "{} is a placeholder", value);
+        }
+
+        for value in &["hunter2", "${", "$(ls", "p4ssw0rd!", "<abc", "ghp_1234567890abcdef"] {
+            assert!(!is_placeholder(value), "{} isn't a placeholder", value);
+        }
+    }
+}
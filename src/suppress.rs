@@ -0,0 +1,173 @@
+use definitions::compile_globs;
+use globset::GlobSet;
+use std::collections::HashSet;
+
+/// Comment marking a line, or the line after it, as not having secrets
+pub const ALLOW_MARKER: &str = "sourcesecrets:allow";
+/// File in a repo listing paths and secrets that shouldn't be reported, read at each commit
+pub const IGNORE_FILE: &str = ".sourcesecretsignore";
+
+//
+// Whether an inline marker on a line, or in a comment on its own on the line before it, allows a
+// match by this rule. `sourcesecrets:allow` allows any rule, and `sourcesecrets:allow
+// rule-a,rule-b` only those.
+//
+pub fn is_allowed_inline(line: &str, previous: Option<&str>, rule_id: &str) -> bool {
+    marker_allows(line, rule_id, false)
+        || previous.is_some_and(|previous| marker_allows(previous, rule_id, true))
+}
+
+fn marker_allows(line: &str, rule_id: &str, own_line: bool) -> bool {
+    let position = match line.find(ALLOW_MARKER) {
+        Some(position) => position,
+        None => return false,
+    };
+
+    // a marker after some code only applies to that code, not the line after it
+    if own_line && line[..position].chars().any(char::is_alphanumeric) {
+        return false;
+    }
+
+    // ids are separated from the marker by a space, and end at anything that can't be in one,
+    // like the `*/` closing a comment
+    let rest = &line[position + ALLOW_MARKER.len()..];
+    let ids: &str = match rest.strip_prefix(' ') {
+        Some(rest) => {
+            let end = rest
+                .find(|c: char| !(c.is_alphanumeric() || "-_.,".contains(c)))
+                .unwrap_or(rest.len());
+            &rest[..end]
+        }
+        None => "",
+    };
+
+    ids.is_empty() || ids.split(',').any(|id| id == rule_id)
+}
+
+//
+// The contents of a .sourcesecretsignore file. Each line is either the fingerprint of a secret
+// that shouldn't be reported, or a glob for paths that shouldn't be searched. Blank lines, lines
+// starting with `#` and globs that can't be compiled are skipped.
+//
+#[derive(Default)]
+pub struct IgnoreFile {
+    globs: Option<GlobSet>,
+    fingerprints: HashSet<String>,
+}
+
+impl IgnoreFile {
+    pub fn parse(contents: &str) -> IgnoreFile {
+        let mut ignore = IgnoreFile::default();
+        let mut globs = Vec::new();
+
+        for line in contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if is_fingerprint(line) {
+                ignore.fingerprints.insert(line.to_lowercase());
+            } else if compile_globs(&[line.to_owned()]).is_ok() {
+                globs.push(line.to_owned());
+            }
+        }
+
+        if !globs.is_empty() {
+            ignore.globs = compile_globs(&globs).ok();
+        }
+
+        ignore
+    }
+
+    pub fn is_ignored_path(&self, path: &str) -> bool {
+        self.globs
+            .as_ref()
+            .is_some_and(|globs| globs.is_match(path))
+    }
+
+    pub fn is_ignored_fingerprint(&self, fingerprint: &str) -> bool {
+        self.fingerprints.contains(fingerprint)
+    }
+}
+
+// Fingerprints are SHA-256 hashes in hex
+fn is_fingerprint(line: &str) -> bool {
+    line.len() == 64 && line.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FINGERPRINT: &str = "63905d97432f580592923cc9e02178dfaaf94bad79d3af83ce6419827c9e0757";
+
+    #[test]
+    fn marker_allows_any_rule_without_ids() {
+        assert!(marker_allows(
+            "token = 'x' # sourcesecrets:allow",
+            "github-token",
+            false
+        ));
+        assert!(marker_allows(
+            "token = 'x' // sourcesecrets:allow",
+            "aws-key",
+            false
+        ));
+        assert!(!marker_allows(
+            "token = 'x' # sourcesecrets",
+            "github-token",
+            false
+        ));
+    }
+
+    #[test]
+    fn marker_allows_only_listed_rules() {
+        let line = "token = 'x' # sourcesecrets:allow github-token,aws-key";
+        assert!(marker_allows(line, "github-token", false));
+        assert!(marker_allows(line, "aws-key", false));
+        assert!(!marker_allows(line, "slack-token", false));
+
+        // ids end at anything that can't be in one
+        let line = "/* sourcesecrets:allow github-token*/ token = 'x'";
+        assert!(marker_allows(line, "github-token", false));
+        assert!(!marker_allows(line, "github-token*/", false));
+        let line = "# sourcesecrets:allow github-token -- fake token";
+        assert!(!marker_allows(line, "fake", false));
+    }
+
+    #[test]
+    fn marker_on_the_line_before_must_be_on_its_own() {
+        assert!(is_allowed_inline(
+            "token = 'x'",
+            Some("# sourcesecrets:allow"),
+            "github-token"
+        ));
+        assert!(!is_allowed_inline(
+            "token = 'x'",
+            Some("other = 'y' # sourcesecrets:allow"),
+            "github-token"
+        ));
+        assert!(!is_allowed_inline("token = 'x'", None, "github-token"));
+    }
+
+    #[test]
+    fn parses_ignore_files() {
+        let ignore = IgnoreFile::parse(&format!(
+            "# test fixtures\n\ntests/fixtures/**\n  {}  \n[invalid\n",
+            FINGERPRINT.to_uppercase()
+        ));
+
+        assert!(ignore.is_ignored_path("tests/fixtures/keys/id_rsa"));
+        assert!(!ignore.is_ignored_path("src/main.rs"));
+        assert!(!ignore.is_ignored_path("# test fixtures"));
+        assert!(ignore.is_ignored_fingerprint(FINGERPRINT));
+        assert!(!ignore.is_ignored_fingerprint(&FINGERPRINT[1..]));
+    }
+
+    #[test]
+    fn empty_ignore_file_ignores_nothing() {
+        let ignore = IgnoreFile::parse("");
+        assert!(!ignore.is_ignored_path("anything"));
+        assert!(!ignore.is_ignored_fingerprint(FINGERPRINT));
+    }
+}